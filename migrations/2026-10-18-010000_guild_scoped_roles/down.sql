@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM roles WHERE guild_id <> '';
+ALTER TABLE roles DROP CONSTRAINT IF EXISTS roles_name_guild_id_key;
+ALTER TABLE roles ADD CONSTRAINT roles_name_key UNIQUE (name);
+ALTER TABLE roles DROP COLUMN guild_id;
+
+DELETE FROM messages WHERE guild_id <> '';
+ALTER TABLE messages DROP CONSTRAINT IF EXISTS messages_name_guild_id_key;
+ALTER TABLE messages ADD CONSTRAINT messages_name_key UNIQUE (name);
+ALTER TABLE messages DROP COLUMN guild_id;
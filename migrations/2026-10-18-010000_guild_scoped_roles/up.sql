@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- An empty guild_id applies to every guild that does not have its own row.
+ALTER TABLE roles ADD COLUMN guild_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE roles DROP CONSTRAINT IF EXISTS roles_name_key;
+ALTER TABLE roles ADD CONSTRAINT roles_name_guild_id_key UNIQUE (name, guild_id);
+
+ALTER TABLE messages ADD COLUMN guild_id TEXT NOT NULL DEFAULT '';
+ALTER TABLE messages DROP CONSTRAINT IF EXISTS messages_name_key;
+ALTER TABLE messages ADD CONSTRAINT messages_name_guild_id_key UNIQUE (name, guild_id);
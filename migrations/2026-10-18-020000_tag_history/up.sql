@@ -0,0 +1,11 @@
+-- Your SQL goes here
+CREATE TABLE IF NOT EXISTS tag_history (
+  id SERIAL PRIMARY KEY,
+  key TEXT NOT NULL,
+  old_value TEXT,
+  new_value TEXT,
+  editor_id TEXT NOT NULL,
+  edited_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS tag_history_key ON tag_history (key);
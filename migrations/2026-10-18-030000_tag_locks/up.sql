@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE tags ADD COLUMN IF NOT EXISTS locked BOOLEAN NOT NULL DEFAULT false;
@@ -1,33 +1,286 @@
 use crate::{
     command_history::CommandHistory,
-    commands::{Args, Auth},
+    commands::{param_error, Args, Menu, MenuFilter},
+    duration::{format_duration, parse_duration_in, HOUR},
     Error,
 };
-use indexmap::IndexMap;
-use serenity::{model::prelude::*, utils::parse_username};
+use serenity::{
+    builder::{
+        CreateAllowedMentions, CreateComponents, CreateEmbed, CreateMessage, EditMessage,
+        ParseValue,
+    },
+    client::bridge::gateway::{ShardId, ShardManager},
+    http::{error::Error as HttpError, StatusCode, Typing},
+    model::prelude::*,
+    prelude::{Mutex, TypeMapKey},
+    utils::parse_username,
+    Error as SerenityError,
+};
+use sqlx::postgres::PgPool;
 use std::sync::Arc;
-use tracing::info;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{error, info};
+
+const MENU_PAGE_SIZE: usize = 10;
+/// The longest slowmode discord allows.  
+const MAX_SLOWMODE: Duration = Duration::from_secs(6 * HOUR);
+/// Room left in a 2000 character message for a help page, after the code block and page
+/// number are added around it.  
+const MENU_PAGE_LEN: usize = 1950;
+/// The most messages discord deletes in one request.  
+const MAX_PURGE: u64 = 100;
+/// How old messages can be for discord to delete them in bulk.  
+const PURGE_MAX_AGE: Duration = Duration::from_secs(14 * 24 * HOUR);
+
+/// How long to wait before trying a request to discord again after a transient failure.  
+///
+/// Rate limits with a `Retry-After` are already waited out by serenity, so this is mostly for
+/// discord's own server errors.  
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// The JSON error code discord answers with for a message that doesn't exist.  
+const UNKNOWN_MESSAGE: isize = 10008;
+
+/// Whether a request to discord failed in a way that is likely to pass when tried again.  
+fn is_transient(error: &SerenityError) -> bool {
+    match error {
+        SerenityError::Http(e) => match &**e {
+            HttpError::UnsuccessfulRequest(response) => {
+                response.status_code.is_server_error()
+                    || response.status_code == StatusCode::TOO_MANY_REQUESTS
+            }
+            HttpError::Request(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether a request failed because the message it refers to was deleted.  
+fn is_unknown_message(error: &SerenityError) -> bool {
+    match error {
+        SerenityError::Http(e) => matches!(
+            &**e,
+            HttpError::UnsuccessfulRequest(response)
+                if response.error.code == UNKNOWN_MESSAGE
+        ),
+        _ => false,
+    }
+}
+
+/// Make a request to discord, trying it once more after `RETRY_DELAY` if it failed transiently.  
+async fn with_retry<T, F, Fut>(mut request: F) -> Result<T, SerenityError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, SerenityError>>,
+{
+    match request().await {
+        Err(e) if is_transient(&e) => {
+            info!("retrying a request to discord: {}", e);
+            sleep(RETRY_DELAY).await;
+            request().await
+        }
+        result => result,
+    }
+}
+
+/// Edit the previous reply to the command, returning `false` if it was deleted in the meantime
+/// and a new one should be sent instead.  
+async fn edit_response<F>(args: &Args, response_id: MessageId, edit: F) -> Result<bool, Error>
+where
+    F: for<'a, 'b> Fn(&'a mut EditMessage<'b>) -> &'a mut EditMessage<'b>,
+{
+    info!("editing message: {:?}", response_id);
+    let edited = with_retry(|| {
+        args.msg
+            .channel_id
+            .edit_message(&args.cx, response_id, &edit)
+    })
+    .await;
+
+    match edited {
+        Ok(_) => Ok(true),
+        Err(e) if is_unknown_message(&e) => {
+            info!("the reply was deleted, sending a new one");
+            Ok(false)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Send a new reply to the command and remember it, so it is edited when the command is.  
+async fn send_response<F>(args: &Args, build: F) -> Result<MessageId, Error>
+where
+    F: for<'a, 'b> Fn(&'a mut CreateMessage<'b>) -> &'a mut CreateMessage<'b>,
+{
+    let response = with_retry(|| {
+        args.msg
+            .channel_id
+            .send_message(&args.cx, |m| build(reply_to(m, &args.msg)))
+    })
+    .await?;
+
+    let mut data = args.cx.data.write().await;
+    let history = data.get_mut::<CommandHistory>().unwrap();
+    history.insert(args.msg.id, response.id);
+    Ok(response.id)
+}
 
 /// Send a reply to the channel the message was received on.  
 pub async fn send_reply(args: Arc<Args>, message: &str) -> Result<(), Error> {
     if let Some(response_id) = response_exists(args.clone()).await {
-        info!("editing message: {:?}", response_id);
-        args.msg
-            .channel_id
-            .edit_message(&args.clone().cx, response_id, |msg| msg.content(message))
-            .await?;
-    } else {
+        // The previous reply may have been an embed
+        let edited = edit_response(&args, response_id, |msg| {
+            msg.content(message).set_embeds(Vec::new())
+        });
+        if edited.await? {
+            return Ok(());
+        }
+    }
+
+    send_response(&args, |m| m.content(message)).await?;
+    Ok(())
+}
+
+/// Show the bot as typing in the channel of the command, until the returned value is dropped.  
+///
+/// For commands that wait on slow requests, failing to show it isn't worth failing them over.  
+pub fn start_typing(args: &Args) -> Option<Typing> {
+    args.msg
+        .channel_id
+        .start_typing(&args.cx.http)
+        .map_err(|e| info!("Unable to show the typing indicator: {}", e))
+        .ok()
+}
+
+/// Send an embed as the reply to the command, or edit the previous reply into it when the
+/// command was edited.  
+pub async fn send_or_edit_embed<F>(args: Arc<Args>, build: F) -> Result<(), Error>
+where
+    F: FnOnce(&mut CreateEmbed) -> &mut CreateEmbed,
+{
+    let mut embed = CreateEmbed::default();
+    build(&mut embed);
+
+    if let Some(response_id) = response_exists(args.clone()).await {
+        let edited = edit_response(&args, response_id, |msg| {
+            msg.content("").set_embed(embed.clone())
+        });
+        if edited.await? {
+            return Ok(());
+        }
+    }
+
+    send_response(&args, |m| m.set_embed(embed.clone())).await?;
+    Ok(())
+}
+
+/// Send a reply with buttons, or edit the previous reply into it when the command was edited,
+/// returning the id of the reply.  
+pub async fn send_or_edit_components(
+    args: Arc<Args>,
+    content: &str,
+    components: CreateComponents,
+) -> Result<MessageId, Error> {
+    if let Some(response_id) = response_exists(args.clone()).await {
+        let edited = edit_response(&args, response_id, |msg| {
+            msg.content(content)
+                .set_embeds(Vec::new())
+                .set_components(components.clone())
+        });
+        if edited.await? {
+            return Ok(response_id);
+        }
+    }
+
+    send_response(&args, |m| {
+        m.content(content).set_components(components.clone())
+    })
+    .await
+}
+
+/// Send a reply that deletes itself after `seconds`, for low value replies like errors.  
+pub async fn send_expiring_reply(
+    args: Arc<Args>,
+    message: &str,
+    seconds: u64,
+) -> Result<(), Error> {
+    send_reply(args.clone(), message).await?;
+
+    if let Some(response_id) = response_exists(args.clone()).await {
+        let cx = args.cx.clone();
         let command_id = args.msg.id;
-        let response = args.clone().msg.channel_id.say(&args.cx, message).await?;
+        let channel_id = args.msg.channel_id;
+
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(seconds)).await;
 
-        let mut data = args.cx.data.write().await;
-        let history = data.get_mut::<CommandHistory>().unwrap();
-        history.insert(command_id, response.id);
+            info!("deleting expired message: {:?}", response_id);
+            let _ = channel_id.delete_message(&cx, response_id).await;
+
+            let mut data = cx.data.write().await;
+            let history = data.get_mut::<CommandHistory>().unwrap();
+            history.remove(&command_id);
+        });
     }
 
     Ok(())
 }
 
+/// Mark a message as a reply to the command `msg` without pinging its author.  
+///
+/// Replies are always sent to `msg.channel_id` so they end up in the same thread as the
+/// command.  
+pub fn reply_to<'a, 'b>(m: &'a mut CreateMessage<'b>, msg: &Message) -> &'a mut CreateMessage<'b> {
+    m.reference_message(msg)
+        .allowed_mentions(|am| allowed_mentions(am).replied_user(false))
+}
+
+/// The mentions the bot is allowed to make in the messages it sends.  
+///
+/// Replies often echo text users wrote, like tags or playground output, so they can only
+/// ping users and never @everyone, @here or a role.  
+pub fn allowed_mentions(am: &mut CreateAllowedMentions) -> &mut CreateAllowedMentions {
+    am.parse(ParseValue::Users)
+}
+
+/// Break up runs of three backticks so `text` can't close the code block it is put in.  
+pub fn escape_code_block(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut backticks = 0;
+
+    for ch in text.chars() {
+        if ch == '`' {
+            if backticks == 2 {
+                escaped.push('\u{200B}');
+                backticks = 0;
+            }
+            backticks += 1;
+        } else {
+            backticks = 0;
+        }
+        escaped.push(ch);
+    }
+    // Trailing backticks would join the ones closing the block
+    if backticks > 0 {
+        escaped.push('\u{200B}');
+    }
+
+    escaped
+}
+
+/// Return the guild the message was sent in, replying with an error when there is none
+/// (for example when a command is sent as a direct message).  
+pub async fn guild_id(args: Arc<Args>) -> Result<GuildId, Error> {
+    match args.msg.guild_id {
+        Some(guild_id) => Ok(guild_id),
+        None => {
+            send_reply(args.clone(), "This command can only be used in a server").await?;
+            Err("command was not sent from a guild".into())
+        }
+    }
+}
+
 async fn response_exists(args: Arc<Args>) -> Option<MessageId> {
     let data = args.cx.data.read().await;
     let history = data.get::<CommandHistory>().unwrap();
@@ -57,116 +310,386 @@ fn check_permission(args: Arc<Args>, role: Option<String>) -> Result<bool, Error
     }
 }
 
+/// Look up the id of the role called `name`.  
+///
+/// A role configured for `guild_id` takes precedence over the default role used by every
+/// guild.  
+pub async fn role_id(
+    db: &PgPool,
+    name: &str,
+    guild_id: Option<GuildId>,
+) -> Result<Option<String>, Error> {
+    let role: Option<(String,)> = sqlx::query_as(
+        "select role from roles where name = $1 and guild_id in ($2, '')
+            order by guild_id desc limit 1",
+    )
+    .bind(name)
+    .bind(guild_key(guild_id))
+    .fetch_optional(db)
+    .await?;
+
+    Ok(role.map(|(role_id,)| role_id))
+}
+
+/// The value stored in the `guild_id` column of guild scoped tables.  
+pub fn guild_key(guild_id: Option<GuildId>) -> String {
+    guild_id.map(|id| id.to_string()).unwrap_or_default()
+}
+
 /// Return whether or not the user is a mod.  
 pub async fn is_mod(args: Arc<Args>) -> Result<bool, Error> {
-    let role: Option<(i32, String, String)> =
-        sqlx::query_as("select * from roles where name = 'mod'")
-            .fetch_optional(&*args.db)
-            .await?;
+    let role = role_id(&args.db, "mod", args.msg.guild_id).await?;
 
-    check_permission(args.clone(), role.map(|(_, role_id, _)| role_id))
+    check_permission(args.clone(), role)
 }
 
 pub async fn is_wg_and_teams(args: Arc<Args>) -> Result<bool, Error> {
-    let role: Option<(i32, String, String)> =
-        sqlx::query_as("select * from roles where name = 'wg_and_teams'")
-            .fetch_optional(&*args.db)
-            .await?;
+    let role = role_id(&args.db, "wg_and_teams", args.msg.guild_id).await?;
 
-    check_permission(args.clone(), role.map(|(_, role_id, _)| role_id))
+    check_permission(args.clone(), role)
 }
 
-pub async fn main_menu(
-    args: Arc<Args>,
-    commands: &IndexMap<&'static str, (&'static str, &'static Auth)>,
-) -> String {
-    use futures::stream::{self, StreamExt};
+/// Return whether or not the user has the administrator permission in the guild.  
+pub async fn is_admin(args: Arc<Args>) -> Result<bool, Error> {
+    let member = args.msg.member(&args.cx).await?;
+
+    Ok(member.permissions(&args.cx)?.administrator())
+}
 
-    let mut menu = format!("Commands:\n");
+/// Render the help menu, split into pages of at most `MENU_PAGE_SIZE` commands that each fit
+/// in a single message.  
+pub async fn main_menu(args: Arc<Args>, commands: &Menu, filter: MenuFilter) -> Vec<String> {
+    use futures::stream::{self, StreamExt};
 
-    menu = stream::iter(commands)
-        .fold(menu, |mut menu, (base_cmd, (description, auth))| {
-            let args_clone = args.clone();
-            async move {
-                if let Ok(true) = auth.call(args_clone).await {
-                    menu += &format!("\t{cmd:<12}{desc}\n", cmd = base_cmd, desc = description);
-                }
-                menu
-            }
+    let mut entries = stream::iter(commands)
+        .filter(|(_, (_, _, protected))| {
+            let shown = match filter {
+                MenuFilter::All => true,
+                MenuFilter::Protected => *protected,
+                MenuFilter::Public => !*protected,
+            };
+            async move { shown }
         })
+        .fold(
+            Vec::new(),
+            |mut entries, (base_cmd, (description, auth, _))| {
+                let args_clone = args.clone();
+                async move {
+                    match auth.call(args_clone).await {
+                        Ok(true) => entries.push(format!(
+                            "\t{cmd:<12}{desc}\n",
+                            cmd = base_cmd,
+                            desc = description
+                        )),
+                        Ok(false) => {}
+                        // Hide the command, but don't mistake a failed check for a denied one
+                        Err(e) => error!("Unable to check permissions for {}: {}", base_cmd, e),
+                    }
+                    entries
+                }
+            },
+        )
         .await;
 
-    menu += &format!("\t{help:<12}This menu\n", help = "?help");
-    menu += "\nType ?help command for more info on a command.";
-    menu += "\n\nAdditional Info:\n";
-    menu += "\tYou can edit your message to the bot and the bot will edit its response.";
-    menu
+    entries.push(format!("\t{help:<12}This menu\n", help = "?help"));
+
+    let header = match filter {
+        MenuFilter::All => "Commands:\n",
+        MenuFilter::Protected => "Moderator commands:\n",
+        MenuFilter::Public => "Public commands:\n",
+    };
+    let footer = "\nType ?help command for more info on a command.\
+        \nType ?help mod or ?help public to list only those commands.\
+        \n\nAdditional Info:\n\
+        \tYou can edit your message to the bot and the bot will edit its response.";
+    let budget = MENU_PAGE_LEN - header.len() - footer.len();
+
+    let mut pages: Vec<Vec<String>> = vec![Vec::new()];
+    for mut entry in entries {
+        if entry.len() > budget {
+            let end = (0..=budget - 4)
+                .rev()
+                .find(|i| entry.is_char_boundary(*i))
+                .unwrap_or(0);
+            entry.truncate(end);
+            entry += "...\n";
+        }
+
+        let page = pages.last_mut().unwrap();
+        let len = page.iter().map(String::len).sum::<usize>();
+        if !page.is_empty() && (page.len() >= MENU_PAGE_SIZE || len + entry.len() > budget) {
+            pages.push(vec![entry]);
+        } else {
+            page.push(entry);
+        }
+    }
+
+    pages
+        .iter()
+        .map(|page| format!("{}{}{}", header, page.concat(), footer))
+        .collect()
 }
 
 /// Set slow mode for a channel.  
 ///
-/// A `seconds` value of 0 will disable slowmode
+/// A `duration` of 0 will disable slowmode
 pub async fn slow_mode(args: Arc<Args>) -> Result<(), Error> {
-    use std::str::FromStr;
-
     if is_mod(args.clone()).await? {
-        let seconds = &args
+        // A plain number is a count of seconds
+        let duration = args
             .params
-            .get("seconds")
-            .ok_or("unable to retrieve seconds param")?
-            .parse::<u64>()?;
+            .get("duration")
+            .ok_or("unable to retrieve duration param")?;
+        let duration = parse_duration_in(duration, Duration::from_secs(1))
+            .map_err(|e| param_error(format!("`{}` isn't a duration: {}", duration, e)))?;
 
-        let channel_name = &args
-            .params
-            .get("channel")
-            .ok_or("unable to retrieve channel param")?;
+        if duration > MAX_SLOWMODE {
+            send_reply(
+                args.clone(),
+                &format!("Slowmode can be at most {}", format_duration(MAX_SLOWMODE)),
+            )
+            .await?;
+            return Ok(());
+        }
+
+        let channel = match text_channel(args.clone(), true).await? {
+            Some(channel) => channel,
+            None => return Ok(()),
+        };
 
-        info!("Applying slowmode to channel {}", &channel_name);
-        ChannelId::from_str(channel_name)?
-            .edit(&args.cx, |c| c.rate_limit_per_user(*seconds))
+        info!("Applying slowmode to channel {}", channel.id);
+        channel
+            .id
+            .edit(&args.cx, |c| c.rate_limit_per_user(duration.as_secs()))
             .await?;
     }
     Ok(())
 }
 
+/// The channels of the current guild that have slowmode enabled.  
+async fn slow_mode_channels(args: Arc<Args>) -> Result<Vec<GuildChannel>, Error> {
+    let guild_id = guild_id(args.clone()).await?;
+
+    let mut channels = guild_id
+        .channels(&args.cx)
+        .await?
+        .into_values()
+        .filter(|channel| channel.rate_limit_per_user.unwrap_or(0) > 0)
+        .collect::<Vec<_>>();
+    channels.sort_by_key(|channel| channel.position);
+
+    Ok(channels)
+}
+
+/// List the channels with slowmode enabled.  
+pub async fn slow_mode_list(args: Arc<Args>) -> Result<(), Error> {
+    let channels = slow_mode_channels(args.clone()).await?;
+
+    if channels.is_empty() {
+        send_reply(args.clone(), "No channels have slowmode enabled.").await?;
+        return Ok(());
+    }
+
+    let list = channels.iter().fold(String::new(), |list, channel| {
+        let delay = Duration::from_secs(channel.rate_limit_per_user.unwrap_or(0));
+        list + &format!("<#{}>: {}\n", channel.id, format_duration(delay))
+    });
+
+    send_reply(args.clone(), &format!("Slowmode is enabled in:\n{}", list)).await?;
+    Ok(())
+}
+
+/// Disable slowmode on every channel of the current guild.  
+pub async fn slow_mode_clear(args: Arc<Args>) -> Result<(), Error> {
+    let channels = slow_mode_channels(args.clone()).await?;
+
+    for channel in &channels {
+        info!("Disabling slowmode in channel {}", channel.id);
+        channel
+            .id
+            .edit(&args.cx, |c| c.rate_limit_per_user(0))
+            .await?;
+    }
+
+    send_reply(
+        args.clone(),
+        &format!("Disabled slowmode in {} channel(s).", channels.len()),
+    )
+    .await?;
+    Ok(())
+}
+
 pub async fn slow_mode_help(args: Arc<Args>) -> Result<(), Error> {
     let help_string = "
 Set slowmode on a channel
 ```
-?slowmode {channel} {seconds}
+?slowmode {channel} {duration}
 ```
 **Example:**
 ```
 ?slowmode #bot-usage 10
 ```
 will set slowmode on the `#bot-usage` channel with a delay of 10 seconds.  
+The duration can also be made of numbers followed by `s`, `m` or `h`, like `1m30s`.  
 
 **Disable slowmode:**
 ```
 ?slowmode #bot-usage 0
 ```
-will disable slowmode on the `#bot-usage` channel.";
+will disable slowmode on the `#bot-usage` channel.
+
+**List or disable every slowmode:**
+```
+?slowmode list
+?slowmode clear
+```";
     send_reply(args.clone(), &help_string).await?;
     Ok(())
 }
 
+/// Look up the channel in the `channel` param, replying with an error unless it is a text
+/// channel of the current guild, or a thread when `threads` is true.  
+async fn text_channel(args: Arc<Args>, threads: bool) -> Result<Option<GuildChannel>, Error> {
+    use std::str::FromStr;
+
+    let channel_name = &args
+        .params
+        .get("channel")
+        .ok_or("unable to retrieve channel param")?;
+
+    let channel_id = ChannelId::from_str(channel_name)
+        .map_err(|_| param_error(format!("`{}` isn't a channel mention", channel_name)))?;
+    let guild_id = guild_id(args.clone()).await?;
+
+    let channel = channel_id
+        .to_channel(&args.cx)
+        .await?
+        .guild()
+        .filter(|channel| channel.guild_id == guild_id)
+        .ok_or("channel is not in this guild")?;
+
+    let valid = match channel.kind {
+        ChannelType::Text | ChannelType::News => true,
+        ChannelType::PublicThread | ChannelType::PrivateThread | ChannelType::NewsThread => threads,
+        _ => false,
+    };
+
+    if valid {
+        Ok(Some(channel))
+    } else {
+        let message = if threads {
+            format!("<#{}> is not a text channel or thread", channel.id)
+        } else {
+            format!("<#{}> is not a text channel", channel.id)
+        };
+        send_reply(args.clone(), &message).await?;
+        Ok(None)
+    }
+}
+
+/// Deny or restore the send messages permission of `@everyone` in a channel.  
+async fn set_channel_lock(args: Arc<Args>, locked: bool) -> Result<(), Error> {
+    // Threads follow the permissions of their parent channel
+    let channel = match text_channel(args.clone(), false).await? {
+        Some(channel) => channel,
+        None => return Ok(()),
+    };
+    let channel_id = channel.id;
+    let guild_id = channel.guild_id;
+
+    // The @everyone role shares its id with the guild
+    let everyone = PermissionOverwriteType::Role(RoleId(guild_id.0));
+    let (mut allow, mut deny) = channel
+        .permission_overwrites
+        .iter()
+        .find(|overwrite| overwrite.kind == everyone)
+        .map(|overwrite| (overwrite.allow, overwrite.deny))
+        .unwrap_or_else(|| (Permissions::empty(), Permissions::empty()));
+
+    if locked {
+        allow.remove(Permissions::SEND_MESSAGES);
+        deny.insert(Permissions::SEND_MESSAGES);
+    } else {
+        deny.remove(Permissions::SEND_MESSAGES);
+    }
+
+    info!(
+        "{} channel {} at the request of {}",
+        if locked { "Locking" } else { "Unlocking" },
+        channel_id,
+        args.msg.author.id
+    );
+    channel_id
+        .create_permission(
+            &args.cx,
+            &PermissionOverwrite {
+                allow,
+                deny,
+                kind: everyone,
+            },
+        )
+        .await?;
+
+    let message = if locked { "Locked" } else { "Unlocked" };
+    send_reply(args.clone(), &format!("{} <#{}>", message, channel_id)).await?;
+    Ok(())
+}
+
+/// Stop everyone without an explicit permission from sending messages in a channel.  
+///
+/// Requires the manage roles permission
+pub async fn lock(args: Arc<Args>) -> Result<(), Error> {
+    if is_mod(args.clone()).await? {
+        set_channel_lock(args, true).await?;
+    }
+    Ok(())
+}
+
+/// Undo `?lock` on a channel.  
+pub async fn unlock(args: Arc<Args>) -> Result<(), Error> {
+    if is_mod(args.clone()).await? {
+        set_channel_lock(args, false).await?;
+    }
+    Ok(())
+}
+
+pub async fn lock_help(args: Arc<Args>) -> Result<(), Error> {
+    let help_string = "
+Stop everyone from sending messages in a channel
+```
+?lock {channel}
+```
+**Example:**
+```
+?lock #general
+```
+will prevent members without an explicit permission to send messages, like mods, from posting in `#general`.  
+
+**Unlock the channel:**
+```
+?unlock #general
+```
+will allow everyone to post in `#general` again.";
+    send_reply(args.clone(), help_string).await?;
+    Ok(())
+}
+
 /// Kick a user from the guild.  
 ///
 /// Requires the kick members permission
 pub async fn kick(args: Arc<Args>) -> Result<(), Error> {
     if is_mod(args.clone()).await? {
-        let user_id = parse_username(
-            &args
-                .params
-                .get("user")
-                .ok_or("unable to retrieve user param")?,
-        )
-        .ok_or("unable to retrieve user id")?;
+        let user = args
+            .params
+            .get("user")
+            .ok_or("unable to retrieve user param")?;
+        let user_id = parse_username(user)
+            .ok_or_else(|| param_error(format!("`{}` isn't a user mention", user)))?;
 
-        if let Some(guild) = args.msg.guild(&args.cx) {
-            info!("Kicking user from guild");
-            guild.kick(&args.cx, UserId::from(user_id)).await?
-        }
+        let guild_id = guild_id(args.clone()).await?;
+
+        info!("Kicking user from guild");
+        guild_id.kick(&args.cx, UserId::from(user_id)).await?
     }
     Ok(())
 }
@@ -185,3 +708,204 @@ will kick a user from the guild.";
     send_reply(args.clone(), &help_string).await?;
     Ok(())
 }
+
+/// Delete the `count` messages posted before the command in its channel, at most 100.  
+///
+/// Discord only deletes messages younger than two weeks in bulk, older ones are left alone.
+/// Requires the manage messages permission.  
+pub async fn purge(args: Arc<Args>) -> Result<(), Error> {
+    let count = args
+        .params
+        .get("count")
+        .ok_or("unable to retrieve count param")?;
+    let count = count
+        .parse::<u64>()
+        .ok()
+        .filter(|count| (1..=MAX_PURGE).contains(count))
+        .ok_or_else(|| {
+            param_error(format!(
+                "`{}` isn't a number of messages from 1 to {}",
+                count, MAX_PURGE
+            ))
+        })?;
+
+    let channel_id = args.msg.channel_id;
+    let oldest = Timestamp::now().unix_timestamp() - PURGE_MAX_AGE.as_secs() as i64;
+    let ids = channel_id
+        .messages(&args.cx, |r| r.before(args.msg.id).limit(count))
+        .await?
+        .into_iter()
+        .filter(|msg| msg.timestamp.unix_timestamp() > oldest)
+        .map(|msg| msg.id)
+        .collect::<Vec<_>>();
+
+    info!("Purging {} messages from {}", ids.len(), channel_id);
+    match &ids[..] {
+        [] => {}
+        [id] => channel_id.delete_message(&args.cx, id).await?,
+        ids => channel_id.delete_messages(&args.cx, ids).await?,
+    }
+
+    send_reply(args.clone(), &format!("Deleted {} message(s).", ids.len())).await
+}
+
+pub async fn purge_help(args: Arc<Args>) -> Result<(), Error> {
+    let help_string = "
+Delete the most recent messages in the channel
+```
+?purge {count}
+```
+**Example:**
+```
+?purge 10
+```
+will delete the 10 messages posted before the command.  Up to 100 messages younger than two weeks can be deleted at once.";
+    send_reply(args.clone(), help_string).await?;
+    Ok(())
+}
+
+/// Send a direct message to the user and report back whether it was delivered.  
+pub async fn dm_test(args: Arc<Args>) -> Result<(), Error> {
+    info!("Sending test direct message to {}", &args.msg.author.id);
+
+    let result = match args.msg.author.create_dm_channel(&args.cx).await {
+        Ok(channel) => channel
+            .say(
+                &args.cx,
+                "This is a test message, I am able to message you.",
+            )
+            .await
+            .map(|_| ()),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => send_reply(args.clone(), "I sent you a direct message.").await?,
+        Err(e) => {
+            info!("Unable to send test direct message: {}", e);
+            send_reply(
+                args.clone(),
+                "I was unable to send you a direct message, check that you allow direct messages from server members.",
+            )
+            .await?
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn dm_test_help(args: Arc<Args>) -> Result<(), Error> {
+    let help_string = "
+Check that the bot is able to send you direct messages
+```
+?dmtest
+```
+**Example:**
+```
+?dmtest
+```
+will send you a direct message and report whether it was delivered.";
+    send_reply(args.clone(), help_string).await?;
+    Ok(())
+}
+
+/// The client's shard manager, kept to read the gateway latency.  
+pub struct ShardManagerContainer;
+
+impl TypeMapKey for ShardManagerContainer {
+    type Value = Arc<Mutex<ShardManager>>;
+}
+
+/// Report the gateway latency and how long a round trip to the database takes.  
+pub async fn ping(args: Arc<Args>) -> Result<(), Error> {
+    let shard_manager = args
+        .cx
+        .data
+        .read()
+        .await
+        .get::<ShardManagerContainer>()
+        .cloned();
+
+    let gateway = match shard_manager {
+        Some(manager) => {
+            let manager = manager.lock().await;
+            let runners = manager.runners.lock().await;
+            runners
+                .get(&ShardId(args.cx.shard_id))
+                .and_then(|runner| runner.latency)
+        }
+        None => None,
+    };
+    // The latency is only known after the first heartbeat
+    let gateway = gateway
+        .map(|latency| format!("{}ms", latency.as_millis()))
+        .unwrap_or_else(|| String::from("unknown"));
+
+    let start = Instant::now();
+    let db = match sqlx::query("select 1").execute(&*args.db).await {
+        Ok(_) => format!("{}ms", start.elapsed().as_millis()),
+        Err(e) => {
+            error!("Database health check failed: {}", e);
+            String::from("unreachable")
+        }
+    };
+
+    send_reply(
+        args.clone(),
+        &format!("Pong! Gateway: {}, DB: {}", gateway, db),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn ping_help(args: Arc<Args>) -> Result<(), Error> {
+    let help_string = "
+Check that the bot is connected to discord and to its database
+```
+?ping
+```
+**Example:**
+```
+?ping
+```
+will reply with the gateway latency and the time a database query takes.";
+    send_reply(args.clone(), help_string).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::{json::json, utils::CustomMessage};
+
+    #[test]
+    fn replies_reference_the_command_in_its_thread() {
+        let thread_id = ChannelId(20);
+        let mut msg = CustomMessage::new();
+        msg.id(MessageId(10))
+            .channel_id(thread_id)
+            .guild_id(GuildId(30))
+            .content("?help");
+        let msg = msg.build();
+
+        let mut m = CreateMessage::default();
+        reply_to(&mut m, &msg);
+
+        let reference = &m.0["message_reference"];
+        assert_eq!(reference["message_id"], json!(msg.id));
+        assert_eq!(reference["channel_id"], json!(thread_id));
+        assert_eq!(m.0["allowed_mentions"]["replied_user"], json!(false));
+    }
+
+    #[test]
+    fn replies_only_ping_users() {
+        let mut msg = CustomMessage::new();
+        msg.id(MessageId(10)).channel_id(ChannelId(20));
+        let msg = msg.build();
+
+        let mut m = CreateMessage::default();
+        reply_to(&mut m, &msg);
+
+        assert_eq!(m.0["allowed_mentions"]["parse"], json!(["users"]));
+    }
+}
@@ -1,4 +1,11 @@
-use crate::{api, commands::Args, text::ban_message, Error, HOUR};
+use crate::{
+    api,
+    commands::{param_error, Args},
+    duration::{parse_duration_in, HOUR},
+    settings,
+    text::ban_message,
+    Error,
+};
 use serenity::{model::prelude::*, prelude::*, utils::parse_username};
 use sqlx::{
     postgres::PgPool,
@@ -13,7 +20,7 @@ use tracing::info;
 pub async fn save_ban(
     user_id: String,
     guild_id: String,
-    hours: u64,
+    duration: Duration,
     db: Arc<PgPool>,
 ) -> Result<(), Error> {
     info!("Recording ban for user {}", &user_id);
@@ -25,7 +32,7 @@ pub async fn save_ban(
     .bind(DateTime::<Utc>::from(SystemTime::now()))
     .bind(DateTime::<Utc>::from(
         SystemTime::now()
-            .checked_add(Duration::new(hours * HOUR, 0))
+            .checked_add(duration)
             .ok_or("out of range Duration for ban end_time")?,
     ))
     .execute(&*db)
@@ -65,55 +72,149 @@ pub async fn unban_users(cx: &Context, db: Arc<PgPool>) -> Result<(), Error> {
     Ok(())
 }
 
+const BAN_RETENTION_SETTING: &str = "ban_retention_days";
+const DEFAULT_BAN_RETENTION_DAYS: u64 = 90;
+
+/// Delete lifted bans that ended more than `ban_retention_days` days ago.  
+///
+/// A retention of 0 keeps every ban.  
+pub async fn prune_bans(db: Arc<PgPool>) -> Result<(), Error> {
+    let retention =
+        settings::get_or(&db, BAN_RETENTION_SETTING, DEFAULT_BAN_RETENTION_DAYS).await?;
+    if retention == 0 {
+        return Ok(());
+    }
+
+    let cutoff = SystemTime::now()
+        .checked_sub(Duration::from_secs(retention.saturating_mul(24 * HOUR)))
+        .ok_or("out of range ban_retention_days")?;
+
+    let pruned = sqlx::query("delete from bans where unbanned = true and end_time < $1")
+        .bind(DateTime::<Utc>::from(cutoff))
+        .execute(&*db)
+        .await?
+        .rows_affected();
+
+    if pruned > 0 {
+        info!("Pruned {} bans older than {} days", pruned, retention);
+    }
+
+    Ok(())
+}
+
+/// Check whether the author of the command is allowed to ban `user`, returning the reason if
+/// they aren't.  
+///
+/// Nobody can ban themselves or the bot, and only the owner of the guild can ban members with
+/// the same or a higher role than their own.  
+async fn ban_refusal(
+    args: Arc<Args>,
+    guild_id: GuildId,
+    user: UserId,
+) -> Result<Option<&'static str>, Error> {
+    if user == args.msg.author.id {
+        return Ok(Some("You can't ban yourself."));
+    }
+
+    if user == args.cx.cache.current_user_id() {
+        return Ok(Some("I can't ban myself."));
+    }
+
+    let is_owner = args
+        .cx
+        .cache
+        .guild_field(guild_id, |guild| guild.owner_id == args.msg.author.id)
+        .unwrap_or(false);
+
+    if !is_owner {
+        // Users that already left the guild have no roles to compare
+        if let Ok(target) = guild_id.member(&args.cx, user).await {
+            let author = guild_id.member(&args.cx, args.msg.author.id).await?;
+            let position = |member: &Member| {
+                member
+                    .highest_role_info(&args.cx.cache)
+                    .map_or(0, |(_, position)| position)
+            };
+
+            if position(&target) >= position(&author) {
+                return Ok(Some(
+                    "You can't ban someone with the same or a higher role than you.",
+                ));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
 /// Temporarily ban an user from the guild.  
 ///
 /// Requires the ban members permission
 pub async fn temp_ban(args: Arc<Args>) -> Result<(), Error> {
-    let user_id = parse_username(
-        &args
-            .params
-            .get("user")
-            .ok_or("unable to retrieve user param")?,
-    )
-    .ok_or("unable to retrieve user id")?;
-
-    use std::str::FromStr;
+    let user = args
+        .params
+        .get("user")
+        .ok_or("unable to retrieve user param")?;
+    let user_id = parse_username(user)
+        .ok_or_else(|| param_error(format!("`{}` isn't a user mention", user)))?;
 
-    let hours = u64::from_str(
-        args.params
-            .get("hours")
-            .ok_or("unable to retrieve hours param")?,
-    )?;
+    // A plain number is a count of hours
+    let duration = args
+        .params
+        .get("duration")
+        .ok_or("unable to retrieve duration param")?;
+    let duration = parse_duration_in(duration, Duration::from_secs(HOUR))
+        .map_err(|e| param_error(format!("`{}` isn't a duration: {}", duration, e)))?;
 
     let reason = args
         .params
         .get("reason")
         .ok_or("unable to retrieve reason param")?;
 
-    if let Some(guild) = args.msg.guild(&args.cx) {
-        info!("Banning user from guild");
-        let user = UserId::from(user_id);
+    let guild_id = api::guild_id(args.clone()).await?;
+    let user = UserId::from(user_id);
 
-        user.create_dm_channel(&args.cx)
-            .await?
-            .say(&args.cx, ban_message(reason, hours))
-            .await?;
+    if let Some(refusal) = ban_refusal(args.clone(), guild_id, user).await? {
+        api::send_reply(args.clone(), refusal).await?;
+        return Ok(());
+    }
 
-        guild.ban(&args.cx, &user, 7).await?;
+    info!("Banning user from guild");
+
+    // The message is sent before banning because discord won't deliver DMs once the user shares
+    // no guild with the bot, but failing to send it mustn't stop the ban
+    let dm = match user.create_dm_channel(&args.cx).await {
+        Ok(channel) => channel
+            .say(&args.cx, ban_message(reason, duration))
+            .await
+            .map(|_| ()),
+        Err(e) => Err(e),
+    };
+
+    guild_id.ban(&args.cx, &user, 7).await?;
+
+    save_ban(
+        format!("{}", user_id),
+        format!("{}", guild_id),
+        duration,
+        args.db.clone(),
+    )
+    .await?;
 
-        save_ban(
-            format!("{}", user_id),
-            format!("{}", guild.id),
-            hours,
-            args.db.clone(),
-        )
-        .await?;
+    if let Err(e) = dm {
+        info!("Unable to send the ban message to {}: {}", user, e);
+        let message = format!(
+            "Banned <@{}>, but the ban message couldn't be sent to them.",
+            user
+        );
+        api::send_reply(args.clone(), &message).await?;
     }
+
     Ok(())
 }
 
 pub async fn help(args: Arc<Args>) -> Result<(), Error> {
-    let hours = 24;
+    let duration = "1d12h";
     let reason = "violating the code of conduct";
 
     let help_string = format!(
@@ -124,16 +225,17 @@ Ban a user for a temporary amount of time
 ```
 **Example:**
 ```
-?ban @someuser {hours} {reason}
+?ban @someuser {duration} {reason}
 ```
-will ban a user for {hours} hours and send them the following message:
+will ban a user for 1 day and 12 hours and send them the following message:
 ```
 {user_message}
 ```
+The duration is made of numbers followed by `s`, `m`, `h`, `d` or `w`.  A plain number is a count of hours.  
 ",
-        command = "?ban {user} {hours} reason...",
-        user_message = ban_message(reason, hours),
-        hours = hours,
+        command = "?ban {user} {duration} reason...",
+        user_message = ban_message(reason, parse_duration_in(duration, Duration::from_secs(HOUR))?),
+        duration = duration,
         reason = reason,
     );
 
@@ -0,0 +1,85 @@
+use crate::{api, commands::Args, settings, Error};
+use serenity::{builder::ParseValue, model::prelude::*};
+use std::{str::FromStr, sync::Arc};
+use tokio::time::{sleep, Duration};
+use tracing::{error, info};
+
+const BROADCAST_CHANNELS_SETTING: &str = "broadcast_channels";
+/// Time left between two sends, to stay well clear of discord's rate limits.  
+const BROADCAST_DELAY: Duration = Duration::from_secs(1);
+
+/// Post a message to every channel listed in the `broadcast_channels` setting.  
+pub async fn broadcast(args: Arc<Args>) -> Result<(), Error> {
+    let message = args
+        .params
+        .get("message")
+        .ok_or("Unable to retrieve param: message")?;
+
+    let channels = settings::get(&args.db, BROADCAST_CHANNELS_SETTING)
+        .await?
+        .unwrap_or_default();
+    let channels = channels.split_whitespace().collect::<Vec<_>>();
+
+    if channels.is_empty() {
+        let reply = "No channels to broadcast to, list them with `?config set broadcast_channels #channel1 #channel2`";
+        api::send_reply(args.clone(), reply).await?;
+        return Ok(());
+    }
+
+    info!("Broadcasting to {} channels", channels.len());
+    let mut failed = Vec::new();
+
+    for (i, channel) in channels.iter().enumerate() {
+        if i > 0 {
+            sleep(BROADCAST_DELAY).await;
+        }
+
+        let sent = match ChannelId::from_str(channel) {
+            Ok(channel_id) => channel_id
+                .send_message(&args.cx, |m| {
+                    // Only users are pinged, so a broadcast can't notify @everyone or whole roles
+                    m.content(message)
+                        .allowed_mentions(|am| am.parse(ParseValue::Users))
+                })
+                .await
+                .map(|_| ())
+                .map_err(Error::from),
+            Err(e) => Err(e.into()),
+        };
+
+        if let Err(e) = sent {
+            error!("Unable to broadcast to {}: {}", channel, e);
+            failed.push(*channel);
+        }
+    }
+
+    let mut reply = format!(
+        "Sent to {} of {} channel(s).",
+        channels.len() - failed.len(),
+        channels.len()
+    );
+    if !failed.is_empty() {
+        reply += &format!(" Failed: {}", failed.join(", "));
+    }
+
+    api::send_reply(args.clone(), &reply).await?;
+    Ok(())
+}
+
+/// Print the help message
+pub async fn help(args: Arc<Args>) -> Result<(), Error> {
+    let help_string = "
+Post a message to every channel in the `broadcast_channels` setting
+```
+?broadcast message...
+```
+**Example:**
+```
+?config set broadcast_channels #announcements #general
+?broadcast The server will be down for maintenance tonight.
+```
+Channels are posted to one at a time, so this can take a few seconds.
+Mentions of users ping them, @everyone, @here and role mentions don't.";
+    api::send_reply(args.clone(), help_string).await?;
+    Ok(())
+}
@@ -17,13 +17,39 @@ impl TypeMapKey for CommandHistory {
     type Value = IndexMap<MessageId, MessageId>;
 }
 
+/// Execute an edited message again if it is a command.  
+///
+/// Updates that don't change the content, like embeds being added or the message being
+/// pinned, are ignored.  
 pub async fn replay_message(
     cx: Context,
+    old: Option<Message>,
     ev: MessageUpdateEvent,
     cmds: &Commands,
     http: Arc<HttpClient>,
     db: Arc<PgPool>,
 ) -> Result<(), Error> {
+    let bot_id = cx.cache.current_user_id();
+
+    if let Some(msg) = edited_command(old.as_ref(), ev, bot_id) {
+        info!(
+            "sending edited message - {:?} {:?}",
+            msg.content, msg.author
+        );
+        cmds.execute(cx, msg, http, db).await;
+    }
+
+    Ok(())
+}
+
+/// The command to run for an edit, if the edit turned a recent message into one.  
+///
+/// Edits of messages written by the bot itself are never run again.  
+fn edited_command(
+    old: Option<&Message>,
+    ev: MessageUpdateEvent,
+    bot_id: UserId,
+) -> Option<Message> {
     let age = ev.timestamp.and_then(|create| {
         ev.edited_timestamp.and_then(|edit| {
             let edit_datetime = *edit;
@@ -35,24 +61,37 @@ pub async fn replay_message(
         })
     });
 
+    let content = ev.content?;
+
+    if old.is_some_and(|old| old.content == content) {
+        return None;
+    }
+
+    // Without the author we can't tell whether the bot edited its own message
+    let author = ev.author?;
+    if author.id == bot_id {
+        return None;
+    }
+
     if age.is_some() && age.unwrap() < MESSAGE_AGE_MAX {
         let mut msg = CustomMessage::new();
         msg.id(ev.id)
             .channel_id(ev.channel_id)
-            .content(ev.content.unwrap_or_default());
+            .author(author)
+            .content(content);
+
+        if let Some(guild_id) = ev.guild_id {
+            msg.guild_id(guild_id);
+        }
 
         let msg = msg.build();
 
         if msg.content.starts_with(PREFIX) {
-            info!(
-                "sending edited message - {:?} {:?}",
-                msg.content, msg.author
-            );
-            cmds.execute(cx, msg, http, db).await;
+            return Some(msg);
         }
     }
 
-    Ok(())
+    None
 }
 
 pub async fn clear_command_history(cx: &Context) -> Result<(), Error> {
@@ -66,3 +105,48 @@ pub async fn clear_command_history(cx: &Context) -> Result<(), Error> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serenity::json::{json, prelude::from_value};
+
+    const BOT_ID: u64 = 1;
+    const MEMBER_ID: u64 = 2;
+
+    fn edit(author_id: u64, content: &str) -> MessageUpdateEvent {
+        from_value(json!({
+            "id": "10",
+            "channel_id": "20",
+            "content": content,
+            "timestamp": "2022-01-01T00:00:00+00:00",
+            "edited_timestamp": "2022-01-01T00:01:00+00:00",
+            "author": {
+                "id": author_id.to_string(),
+                "username": "someone",
+                "discriminator": "0001",
+                "avatar": null,
+                "bot": author_id == BOT_ID,
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn edited_commands_are_run_again() {
+        let msg = edited_command(None, edit(MEMBER_ID, "?help"), UserId(BOT_ID)).unwrap();
+        assert_eq!(msg.content, "?help");
+        assert_eq!(msg.author.id, UserId(MEMBER_ID));
+        assert_eq!(msg.channel_id, ChannelId(20));
+    }
+
+    #[test]
+    fn edits_by_the_bot_are_not_run_again() {
+        assert!(edited_command(None, edit(BOT_ID, "?help"), UserId(BOT_ID)).is_none());
+    }
+
+    #[test]
+    fn edits_that_are_not_commands_are_ignored() {
+        assert!(edited_command(None, edit(MEMBER_ID, "hello"), UserId(BOT_ID)).is_none());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{
-    api,
+    api, confirmation, menu, metrics, settings,
     state_machine::{CharacterSet, StateMachine},
     Error,
 };
@@ -7,10 +7,15 @@ use indexmap::IndexMap;
 use reqwest::Client as HttpClient;
 use serenity::{model::channel::Message, prelude::Context};
 use sqlx::postgres::PgPool;
-use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc};
-use tracing::{error, info};
+use std::{collections::HashMap, fmt, future::Future, pin::Pin, sync::Arc};
+use tracing::{error, info, info_span, Instrument};
 
 pub const PREFIX: &str = "?";
+/// How long the reply to a command the user isn't allowed to run is shown.  
+const PERMISSION_DENIED_SECS: u64 = 10;
+/// The reply to commands limited to some roles, like `?ban`, when they are sent in a direct
+/// message.  
+const GUILD_ONLY_REPLY: &str = "This command only works in a server";
 
 type ResultFuture<T, E> = Pin<Box<dyn Future<Output = Result<T, E>> + Send>>;
 
@@ -35,13 +40,31 @@ pub type Auth = dyn AsyncFn<bool> + Send + Sync;
 pub enum CommandKind {
     Base,
     Protected,
-    Help,
+    Help(MenuFilter),
 }
 
+/// Which commands the help menu lists.  
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MenuFilter {
+    All,
+    /// Only commands that check permissions, like the mod commands.  
+    Protected,
+    /// Only commands anyone can run.  
+    Public,
+}
+
+/// The description, auth check and whether the command is protected, for each help entry.  
+pub type Menu = IndexMap<&'static str, (&'static str, &'static Auth, bool)>;
+
 pub struct Command {
     pub kind: CommandKind,
     pub auth: &'static Auth,
     pub handler: &'static Handler,
+    pub confirm: bool,
+    /// The pattern the command was registered with, used to label its metrics.  
+    pub pattern: &'static str,
+    /// An example invocation, shown when the arguments of the command can't be understood.  
+    pub example: Option<&'static str>,
 }
 
 impl Command {
@@ -50,6 +73,9 @@ impl Command {
             kind: CommandKind::Base,
             auth: &|_| async { Ok(true) },
             handler,
+            confirm: false,
+            pattern: "",
+            example: None,
         }
     }
 
@@ -58,16 +84,58 @@ impl Command {
             kind: CommandKind::Protected,
             auth,
             handler,
+            confirm: false,
+            pattern: "",
+            example: None,
         }
     }
 
     pub fn help() -> Self {
+        Self::filtered_help(MenuFilter::All)
+    }
+
+    /// The help menu, limited to the commands matching `filter`.  
+    pub fn filtered_help(filter: MenuFilter) -> Self {
         Self {
-            kind: CommandKind::Help,
+            kind: CommandKind::Help(filter),
             auth: &|_| async { Ok(true) },
             handler: &|_| async { Ok(()) },
+            confirm: false,
+            pattern: "",
+            example: None,
         }
     }
+
+    /// Require the invoking user to confirm the command with a reaction before it runs, even
+    /// when it isn't listed in the `confirm_commands` setting.  
+    pub fn with_confirmation(mut self, confirm: bool) -> Self {
+        self.confirm = confirm;
+        self
+    }
+
+    /// Show `example` along with the usage when the arguments of the command can't be
+    /// understood, set on the command passed to `Commands::help`.  
+    pub fn with_example(mut self, example: &'static str) -> Self {
+        self.example = Some(example);
+        self
+    }
+}
+
+/// An argument of a command that couldn't be understood, answered with how to use the command
+/// instead of only being logged.  
+#[derive(Debug)]
+pub struct ParamError(String);
+
+impl fmt::Display for ParamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParamError {}
+
+pub fn param_error(message: impl Into<String>) -> Error {
+    Box::new(ParamError(message.into()))
 }
 
 pub struct Args {
@@ -76,19 +144,32 @@ pub struct Args {
     pub params: HashMap<&'static str, String>,
     pub http: Arc<HttpClient>,
     pub db: Arc<PgPool>,
+    /// The pattern of the command and an example of it, for replies to arguments that can't be
+    /// understood.  
+    pub usage: String,
 }
 
-async fn execute_command(args: Arc<Args>, handler: &'static Handler) {
+pub async fn execute_command(args: Arc<Args>, handler: &'static Handler) {
     info!("Executing command");
-    if let Err(e) = handler.call(args).await {
+    if let Err(e) = handler.call(args.clone()).await {
+        metrics::record_error();
         error!("{}", e);
+
+        if e.downcast_ref::<ParamError>().is_some() {
+            let message = format!("{}\n{}", e, args.usage);
+            if let Err(e) = api::send_reply(args.clone(), &message).await {
+                error!("{}", e);
+            }
+        }
     }
 }
 
 pub struct Commands {
     state_machine: StateMachine,
     command_map: HashMap<usize, Arc<Command>>,
-    menu: Option<IndexMap<&'static str, (&'static str, &'static Auth)>>,
+    menu: Option<Menu>,
+    /// The examples given to `help`, by command name.  
+    examples: HashMap<String, &'static str>,
 }
 
 impl Commands {
@@ -97,6 +178,7 @@ impl Commands {
             state_machine: StateMachine::new(),
             command_map: HashMap::new(),
             menu: Some(IndexMap::new()),
+            examples: HashMap::new(),
         }
     }
 
@@ -107,7 +189,10 @@ impl Commands {
         let mut reused_space_state = None;
         let mut opt_final_states = vec![];
 
-        let handler = Arc::new(command);
+        let handler = Arc::new(Command {
+            pattern: input,
+            ..command
+        });
 
         input
             .split(' ')
@@ -184,44 +269,154 @@ impl Commands {
         info!("Adding command ?help {}", &base_cmd);
         let mut state = 0;
 
+        if let Some(example) = command.example {
+            self.examples.insert(settings::command_name(cmd), example);
+        }
+
         self.menu.as_mut().map(|menu| {
-            menu.insert(cmd, (desc, command.auth));
+            let protected = matches!(command.kind, CommandKind::Protected);
+            menu.insert(cmd, (desc, command.auth, protected));
             menu
         });
 
         state = self.add_help_menu(base_cmd, state);
         self.state_machine.set_final_state(state);
-        self.command_map.insert(state, Arc::new(command));
+        self.command_map.insert(
+            state,
+            Arc::new(Command {
+                pattern: "?help",
+                ..command
+            }),
+        );
+    }
+
+    /// Find the command `input` invokes, along with the params parsed out of it.  
+    ///
+    /// When several patterns match, the one registered first wins.  
+    pub fn find(&self, input: &str) -> Option<(Arc<Command>, HashMap<&'static str, String>)> {
+        let matched = self.state_machine.process(input)?;
+        let command = self.command_map.get(&matched.state)?;
+        Some((command.clone(), matched.params))
+    }
+
+    /// Find the help of the command `input` names, for messages that don't match any of the
+    /// command's patterns, like `?tags foo bar`.  
+    fn find_help(&self, input: &str) -> Option<(Arc<Command>, HashMap<&'static str, String>)> {
+        let name = input.strip_prefix(PREFIX)?.split_whitespace().next()?;
+        let found = self.find(&format!("{}help {}", PREFIX, name))?;
+        info!("No pattern of `{}` matched, showing its help", name);
+        Some(found)
+    }
+
+    pub async fn execute(
+        &self,
+        cx: Context,
+        mut msg: Message,
+        http: Arc<HttpClient>,
+        db: Arc<PgPool>,
+    ) {
+        // Group the log lines of a single command execution by the id of the message
+        let span = info_span!("command", request_id = %msg.id);
+        async move {
+            self.expand_alias(&db, &mut msg).await;
+            self.dispatch(cx, msg, http, db).await
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// Replace an alias set with `?alias` at the start of the message with the command it
+    /// stands for.  Messages that are already a command are left alone, so aliases can't shadow
+    /// commands.  
+    async fn expand_alias(&self, db: &PgPool, msg: &mut Message) {
+        if !msg.content.starts_with(PREFIX) || self.find(&msg.content).is_some() {
+            return;
+        }
+
+        match settings::expand_alias(db, &msg.content).await {
+            Ok(Some(content)) => {
+                info!("Expanded alias to: {}", content);
+                msg.content = content;
+            }
+            Ok(None) => {}
+            Err(e) => error!("Unable to expand the command aliases: {}", e),
+        }
     }
 
-    pub async fn execute(&self, cx: Context, msg: Message, http: Arc<HttpClient>, db: Arc<PgPool>) {
+    async fn dispatch(&self, cx: Context, msg: Message, http: Arc<HttpClient>, db: Arc<PgPool>) {
         let message = &msg.content;
         if !msg.is_own(&cx) && message.starts_with(PREFIX) {
-            if let Some(matched) = self.state_machine.process(message) {
+            let found = self.find(message).or_else(|| self.find_help(message));
+            if let Some((command, params)) = found {
                 info!("Processing command: {}", message);
+                let usage = self.usage(command.pattern);
                 let args = Arc::new(Args {
                     cx,
                     msg,
-                    params: matched.params,
+                    params,
                     http: http.clone(),
                     db: db.clone(),
+                    usage,
                 });
 
-                let command = self.command_map.get(&matched.state).unwrap();
+                match settings::is_disabled(&db, &settings::command_name(command.pattern)).await {
+                    Ok(false) => {}
+                    Ok(true) => {
+                        info!("Not executing command, disabled");
+                        if let Err(e) =
+                            api::send_reply(args.clone(), "That command is temporarily disabled")
+                                .await
+                        {
+                            error!("{}", e);
+                        }
+                        return;
+                    }
+                    Err(e) => error!("Unable to check if the command is disabled: {}", e),
+                }
+
+                let confirm = command.confirm
+                    || settings::needs_confirmation(&db, &settings::command_name(command.pattern))
+                        .await
+                        .unwrap_or_else(|e| {
+                            error!("Unable to check if the command needs confirmation: {}", e);
+                            false
+                        });
+
+                metrics::record_command(command.pattern);
 
                 match command.kind {
+                    CommandKind::Base if confirm => {
+                        if let Err(e) = confirmation::request(args.clone(), command.handler).await {
+                            error!("{}", e);
+                        }
+                    }
                     CommandKind::Base => {
                         execute_command(args.clone(), command.handler).await;
                     }
+                    // The roles and permissions they check only exist in a server
+                    CommandKind::Protected if args.msg.guild_id.is_none() => {
+                        info!("Not executing command, sent in a direct message");
+                        if let Err(e) = api::send_reply(args.clone(), GUILD_ONLY_REPLY).await {
+                            error!("{}", e);
+                        }
+                    }
                     CommandKind::Protected => match command.auth.call(args.clone()).await {
+                        Ok(true) if confirm => {
+                            if let Err(e) =
+                                confirmation::request(args.clone(), command.handler).await
+                            {
+                                error!("{}", e);
+                            }
+                        }
                         Ok(true) => {
                             execute_command(args.clone(), command.handler).await;
                         }
                         Ok(false) => {
                             info!("Not executing command, unauthorized");
-                            if let Err(e) = api::send_reply(
+                            if let Err(e) = api::send_expiring_reply(
                                 args.clone(),
                                 "You do not have permission to run this command",
+                                PERMISSION_DENIED_SECS,
                             )
                             .await
                             {
@@ -230,12 +425,10 @@ impl Commands {
                         }
                         Err(e) => error!("{}", e),
                     },
-                    CommandKind::Help => {
-                        let output =
-                            api::main_menu(args.clone(), self.menu.as_ref().unwrap()).await;
-                        if let Err(e) =
-                            api::send_reply(args.clone(), &format!("```{}```", &output)).await
-                        {
+                    CommandKind::Help(filter) => {
+                        let pages =
+                            api::main_menu(args.clone(), self.menu.as_ref().unwrap(), filter).await;
+                        if let Err(e) = menu::send_menu(args.clone(), pages).await {
                             error!("{}", e)
                         }
                     }
@@ -244,6 +437,15 @@ impl Commands {
         }
     }
 
+    /// How to use the command registered with `pattern`, with its example if it has one.  
+    fn usage(&self, pattern: &str) -> String {
+        let mut usage = format!("Usage: `{}`", pattern);
+        if let Some(example) = self.examples.get(&settings::command_name(pattern)) {
+            usage += &format!("\nExample: `{}`", example);
+        }
+        usage
+    }
+
     fn add_space(&mut self, mut state: usize, i: usize) -> usize {
         if i > 0 {
             let char_set = CharacterSet::from_chars(&[' ', '\n']);
@@ -374,16 +576,28 @@ impl Commands {
             state = self.state_machine.add(state, CharacterSet::from_char(c));
         });
         state = self.state_machine.add(state, CharacterSet::from_char('='));
-        state = self.state_machine.add(state, CharacterSet::from_char('"'));
+        let quote = self.state_machine.add(state, CharacterSet::from_char('"'));
 
         let mut char_set = CharacterSet::any();
-        char_set.remove(&['"']);
-        state = self.state_machine.add(state, char_set);
-        self.state_machine.add_next_state(state, state);
-        self.state_machine.start_parse(state, name);
-        self.state_machine.end_parse(state);
+        char_set.remove(&['"', '\\']);
+        let value = self.state_machine.add(quote, char_set);
+        self.state_machine.add_next_state(value, value);
+
+        // `\"` and `\\` are read as part of the value instead of ending it
+        let escape = self.state_machine.add(quote, CharacterSet::from_char('\\'));
+        self.state_machine.add_next_state(value, escape);
+        let escaped = self.state_machine.add(escape, CharacterSet::any());
+        self.state_machine.add_next_state(escaped, value);
+        self.state_machine.add_next_state(escaped, escape);
+
+        for state in [value, escape, escaped] {
+            self.state_machine.start_parse(state, name);
+            self.state_machine.end_parse(state);
+            self.state_machine.unescape(state);
+        }
 
-        state = self.state_machine.add(state, CharacterSet::from_char('"'));
+        state = self.state_machine.add(value, CharacterSet::from_char('"'));
+        self.state_machine.add_next_state(escaped, state);
 
         state
     }
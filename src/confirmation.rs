@@ -0,0 +1,132 @@
+use crate::{
+    api,
+    commands::{execute_command, Args, Handler},
+    settings, Error,
+};
+use serenity::{model::prelude::*, prelude::*};
+use std::{collections::HashMap, sync::Arc};
+use tokio::time::{sleep, Duration};
+use tracing::{info, info_span, Instrument};
+
+const CONFIRMATION_TIMEOUT_SETTING: &str = "confirmation_timeout";
+const DEFAULT_CONFIRMATION_TIMEOUT: u64 = 60;
+const CONFIRM: char = '✅';
+const CANCEL: char = '❌';
+
+pub struct PendingConfirmation {
+    args: Arc<Args>,
+    handler: &'static Handler,
+}
+
+pub struct PendingConfirmations;
+
+impl TypeMapKey for PendingConfirmations {
+    type Value = HashMap<MessageId, PendingConfirmation>;
+}
+
+async fn take_pending(cx: &Context, message_id: MessageId) -> Option<PendingConfirmation> {
+    let mut data = cx.data.write().await;
+    let pending = data.get_mut::<PendingConfirmations>().unwrap();
+    pending.remove(&message_id)
+}
+
+/// Ask the user who invoked a command to confirm it before running `handler`.  
+///
+/// The command is dropped if it is not confirmed within the number of seconds in the
+/// `confirmation_timeout` setting.  
+pub async fn request(args: Arc<Args>, handler: &'static Handler) -> Result<(), Error> {
+    let timeout = settings::get_or(
+        &args.db,
+        CONFIRMATION_TIMEOUT_SETTING,
+        DEFAULT_CONFIRMATION_TIMEOUT,
+    )
+    .await?;
+
+    let summary = format!(
+        "React with {} to run `{}` or {} to cancel.  This request expires in {} seconds.",
+        CONFIRM, args.msg.content, CANCEL, timeout
+    );
+
+    let message = args
+        .msg
+        .channel_id
+        .send_message(&args.cx, |m| api::reply_to(m, &args.msg).content(summary))
+        .await?;
+    message.react(&args.cx, CONFIRM).await?;
+    message.react(&args.cx, CANCEL).await?;
+
+    {
+        let mut data = args.cx.data.write().await;
+        let pending = data.get_mut::<PendingConfirmations>().unwrap();
+        pending.insert(
+            message.id,
+            PendingConfirmation {
+                args: args.clone(),
+                handler,
+            },
+        );
+    }
+
+    let cx = args.cx.clone();
+    tokio::spawn(async move {
+        sleep(Duration::from_secs(timeout)).await;
+
+        if take_pending(&cx, message.id).await.is_some() {
+            info!("Confirmation expired for message {:?}", message.id);
+            let _ = message
+                .channel_id
+                .edit_message(&cx, message.id, |m| {
+                    m.content("Confirmation expired, the command was not run.")
+                })
+                .await;
+        }
+    });
+
+    Ok(())
+}
+
+/// Run or cancel a pending command when its invoker reacts to the confirmation message.  
+pub async fn handle_reaction(cx: &Context, reaction: &Reaction) -> Result<(), Error> {
+    let confirmed = reaction.emoji == ReactionType::from(CONFIRM);
+    if !confirmed && reaction.emoji != ReactionType::from(CANCEL) {
+        return Ok(());
+    }
+
+    let is_invoker = {
+        let data = cx.data.read().await;
+        let pending = data.get::<PendingConfirmations>().unwrap();
+        pending
+            .get(&reaction.message_id)
+            .map(|pending| Some(pending.args.msg.author.id) == reaction.user_id)
+            .unwrap_or(false)
+    };
+
+    if !is_invoker {
+        return Ok(());
+    }
+
+    if let Some(pending) = take_pending(cx, reaction.message_id).await {
+        let content = if confirmed {
+            "Confirmed."
+        } else {
+            "Cancelled, the command was not run."
+        };
+
+        reaction
+            .channel_id
+            .edit_message(cx, reaction.message_id, |m| m.content(content))
+            .await?;
+
+        if confirmed {
+            let span = info_span!("command", request_id = %pending.args.msg.id);
+            async {
+                info!("Running confirmed command");
+                execute_command(pending.args, pending.handler).await;
+            }
+            .instrument(span)
+            .await;
+        }
+    }
+
+    Ok(())
+}
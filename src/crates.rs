@@ -1,77 +1,1590 @@
-use crate::{api, commands::Args, Error};
-use reqwest::header;
-use serde::Deserialize;
-use std::sync::Arc;
+use crate::{api, commands::Args, slash, Error};
+use reqwest::{header, Client as HttpClient, StatusCode};
+use serde::{Deserialize, Serialize};
+use serenity::{
+    builder::CreateEmbed,
+    model::application::interaction::application_command::ApplicationCommandInteraction,
+    prelude::*, utils::Colour,
+};
+use sqlx::types::chrono::{DateTime, Utc};
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+use tokio::time::timeout;
 use tracing::info;
 
 const USER_AGENT: &str = "rust-lang/discord-mods-bot";
+const CRATE_LIST_SIZE: &str = "10";
+const MAX_DEPENDENCIES_PER_KIND: usize = 10;
+const MAX_RELATED_CRATES: usize = 4;
+const MAX_MAJOR_VERSIONS: usize = 3;
+/// How many of the default features are listed for a crate.  
+const MAX_DEFAULT_FEATURES: usize = 10;
+/// How many keywords or categories are shown for a crate.  
+const MAX_CRATE_TAGS: usize = 5;
+/// How many names are offered when no search result is named like the query.  
+const MAX_SUGGESTIONS: usize = 3;
+/// How similar, from 0 to 1, a result's name has to be to the query to be shown without asking.  
+const MIN_NAME_SIMILARITY: f64 = 0.6;
+
+/// Descriptions shorter than this are followed by the start of the crate's README.  
+const SPARSE_DESCRIPTION_LEN: usize = 80;
+/// How much of the README is shown, cut at the last word that fits.  
+const README_EXCERPT_LEN: usize = 300;
+/// The longest message discord allows, which `?crate text` has to fit in.  
+const CRATE_TEXT_LEN: usize = 2000;
+/// How long each lookup of a crate's details may take before it's left out.  
+const DETAILS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Crates first published within this many days get a note to double check them.  
+const NEW_CRATE_DAYS: i64 = 30;
+/// Crates with fewer downloads than this get a note to double check them.  
+const LOW_DOWNLOADS: u64 = 1000;
+
+/// Crates updated within this many days are shown in green.  
+const FRESH_DAYS: i64 = 30;
+/// Crates updated within this many days are shown in yellow, older ones in red.  
+const STALE_DAYS: i64 = 365;
 
 #[derive(Debug, Deserialize)]
 struct Crates {
     crates: Vec<Crate>,
+    meta: Meta,
+}
+
+#[derive(Debug, Deserialize)]
+struct Meta {
+    /// How many crates matched, including those on later pages.  
+    total: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: Crate,
+}
+
+#[derive(Debug, Deserialize)]
+struct Dependencies {
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Dependency {
+    crate_id: String,
+    req: String,
+    kind: String,
+    optional: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Versions {
+    versions: Vec<Version>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Version {
+    num: String,
+    yanked: bool,
+    license: Option<String>,
+    /// The edition from the manifest, only recorded for versions published since 2023.  
+    #[serde(default)]
+    edition: Option<String>,
+    #[serde(default)]
+    rust_version: Option<String>,
+    #[serde(default)]
+    features: HashMap<String, Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DocsStatus {
+    doc_status: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct VulnerabilityQuery<'a> {
+    package: Package<'a>,
+    version: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+struct Package<'a> {
+    name: &'a str,
+    ecosystem: &'static str,
+}
+
+#[derive(Debug, Deserialize)]
+struct Vulnerabilities {
+    #[serde(default)]
+    vulns: Vec<Vulnerability>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Vulnerability {
+    id: String,
+}
+
+/// Security advisory ids for each crate name and version that has been looked up.  
+pub struct AdvisoryCache;
+
+impl TypeMapKey for AdvisoryCache {
+    type Value = HashMap<(String, String), Vec<String>>;
+}
+
+/// Whether crates get a lib.rs link next to the crates.io one, from the `lib_rs_links` config.  
+pub struct LibRsLinks;
+
+impl TypeMapKey for LibRsLinks {
+    type Value = bool;
+}
+
+async fn lib_rs_links(cx: &Context) -> bool {
+    cx.data
+        .read()
+        .await
+        .get::<LibRsLinks>()
+        .copied()
+        .unwrap_or(false)
+}
+
+#[derive(Debug, Deserialize)]
+struct Crate {
+    id: String,
+    name: String,
+    newest_version: String,
+    max_stable_version: Option<String>,
+    #[serde(rename = "updated_at")]
+    updated: String,
+    #[serde(rename = "created_at")]
+    created: String,
+    downloads: u64,
+    #[serde(default)]
+    description: String,
+    documentation: Option<String>,
+    repository: Option<String>,
+    /// Only included when looking up a crate by name, not in search results.  
+    #[serde(default)]
+    keywords: Option<Vec<String>>,
+    /// Only included when looking up a crate by name, not in search results.  
+    #[serde(default)]
+    categories: Option<Vec<String>>,
+}
+
+async fn get_crates(http: &HttpClient, query: &[(&str, &str)]) -> Result<Vec<Crate>, Error> {
+    Ok(get_crate_list(http, query).await?.crates)
+}
+
+async fn get_crate_list(http: &HttpClient, query: &[(&str, &str)]) -> Result<Crates, Error> {
+    let crate_list = http
+        .get("https://crates.io/api/v1/crates")
+        .header(header::USER_AGENT, USER_AGENT)
+        .query(query)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Crates>()
+        .await?;
+
+    Ok(crate_list)
+}
+
+/// Look up a crate by its exact name.  
+async fn get_exact_crate(http: &HttpClient, name: &str) -> Result<Option<Crate>, Error> {
+    let is_crate_name = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if !is_crate_name {
+        return Ok(None);
+    }
+
+    info!("looking up crate `{}`", name);
+
+    let response = http
+        .get(format!("https://crates.io/api/v1/crates/{}", name))
+        .header(header::USER_AGENT, USER_AGENT)
+        .send()
+        .await?;
+
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+
+    let krate = response
+        .error_for_status()?
+        .json::<CrateResponse>()
+        .await?
+        .krate;
+
+    Ok(Some(krate))
+}
+
+/// Look up a crate by its exact name, falling back to searching for it if there is no crate
+/// with that name.  
+///
+/// A search result named like the query is preferred over the best scoring result.  
+async fn get_crate(http: &HttpClient, query: &str) -> Result<Option<Crate>, Error> {
+    Ok(search_crate(http, query).await?.krate)
+}
+
+/// A crate found by `search_crate`.  
+struct Search {
+    krate: Option<Crate>,
+    /// How many crates matched, when it had to search.  
+    matches: Option<u64>,
+    /// The closest names, when none of the results is named much like the query.  
+    suggestions: Vec<String>,
+}
+
+fn normalize_name(name: &str) -> String {
+    name.trim().to_lowercase().replace(['-', ' '], "_")
+}
+
+/// How similar two crate names are, from 0 for nothing in common to 1 for the same name.  
+fn name_similarity(query: &str, name: &str) -> f64 {
+    let (query, name) = (normalize_name(query), normalize_name(name));
+    if query == name {
+        return 1.0;
+    }
+
+    let (query, name) = (
+        query.chars().collect::<Vec<_>>(),
+        name.chars().collect::<Vec<_>>(),
+    );
+    // Levenshtein distance, keeping a single row of the table
+    let mut row = (0..=name.len()).collect::<Vec<_>>();
+    for (i, q) in query.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, n) in name.iter().enumerate() {
+            let substitution = diagonal + usize::from(q != n);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+
+    1.0 - row[name.len()] as f64 / query.len().max(name.len()) as f64
+}
+
+/// Like `get_crate`, also returning how many crates matched when it had to search, and the
+/// closest names when none of the results looks like what was asked for.  
+async fn search_crate(http: &HttpClient, query: &str) -> Result<Search, Error> {
+    if let Some(krate) = get_exact_crate(http, query).await? {
+        return Ok(Search {
+            krate: Some(krate),
+            matches: None,
+            suggestions: Vec::new(),
+        });
+    }
+
+    info!("searching for crate `{}`", query);
+
+    let Crates { crates, meta } = get_crate_list(http, &[("q", query)]).await?;
+    let name = normalize_name(query);
+    let best = crates
+        .iter()
+        .position(|krate| normalize_name(&krate.name) == name)
+        .unwrap_or(0);
+
+    let names = crates
+        .iter()
+        .map(|krate| &krate.name[..])
+        .collect::<Vec<_>>();
+    let suggestions = suggestions(query, &names);
+
+    Ok(Search {
+        krate: crates.into_iter().nth(best),
+        matches: Some(meta.total),
+        suggestions,
+    })
+}
+
+/// The names closest to the query, when none of them is similar enough to show without
+/// asking.  
+fn suggestions(query: &str, names: &[&str]) -> Vec<String> {
+    let mut scored = names
+        .iter()
+        .map(|name| (name_similarity(query, name), name))
+        .collect::<Vec<_>>();
+    // Stable, so results that are as similar keep the order crates.io ranked them in
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    match scored.first() {
+        Some((similarity, _)) if *similarity < MIN_NAME_SIMILARITY => scored
+            .iter()
+            .take(MAX_SUGGESTIONS)
+            .map(|(_, name)| name.to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Ask which of the closest names was meant, when none of them is close to the query.  
+fn suggestions_message(query: &str, suggestions: &[String]) -> String {
+    let names = suggestions
+        .iter()
+        .map(|name| format!("`{}`", name))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "No crate is named like `{}`, did you mean {}? Use `?crate exact {{name}}` to pick one.",
+        query, names
+    )
+}
+
+impl Crate {
+    fn version(&self) -> &str {
+        self.max_stable_version
+            .as_ref()
+            .unwrap_or(&self.newest_version)
+    }
+
+    /// The description, or a placeholder for crates published without one.  
+    fn description(&self) -> &str {
+        match self.description.trim() {
+            "" => "_No description provided._",
+            description => description,
+        }
+    }
+
+    fn updated_at(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.updated)
+            .ok()
+            .map(|updated| updated.with_timezone(&Utc))
+    }
+
+    /// A note for crates that are brand new or barely used, which typosquats tend to be.  
+    fn caution(&self) -> Option<&'static str> {
+        let is_new = DateTime::parse_from_rfc3339(&self.created)
+            .ok()
+            .map(|created| Utc::now().signed_duration_since(created).num_days() < NEW_CRATE_DAYS)
+            .unwrap_or(false);
+
+        match (is_new, self.downloads < LOW_DOWNLOADS) {
+            (true, true) => {
+                Some("New crate with few downloads, check that it is the one you meant")
+            }
+            (true, false) => Some("New crate, check that it is the one you meant"),
+            (false, true) => Some("Crate with few downloads, check that it is the one you meant"),
+            (false, false) => None,
+        }
+    }
+}
+
+/// Describe how long ago `time` was, like "3 days ago".  
+///
+/// Times in the future, from clock skew between us and crates.io, are treated as "just now".  
+fn time_ago(time: DateTime<Utc>) -> String {
+    let elapsed = Utc::now().signed_duration_since(time);
+    let days = elapsed.num_days();
+
+    let (count, unit) = if elapsed.num_minutes() < 1 {
+        return String::from("just now");
+    } else if elapsed.num_hours() < 1 {
+        (elapsed.num_minutes(), "minute")
+    } else if days < 1 {
+        (elapsed.num_hours(), "hour")
+    } else if days < 30 {
+        (days, "day")
+    } else if days < 365 {
+        (days / 30, "month")
+    } else {
+        (days / 365, "year")
+    };
+
+    format!(
+        "{} {}{} ago",
+        count,
+        unit,
+        if count == 1 { "" } else { "s" }
+    )
+}
+
+/// Check whether docs.rs was able to build the documentation for a version of a crate.  
+///
+/// Returns `None` if docs.rs could not be reached.
+async fn docs_built(http: &HttpClient, krate: &Crate) -> Option<bool> {
+    let status = http
+        .get(format!(
+            "https://docs.rs/crate/{}/{}/status.json",
+            krate.name,
+            krate.version()
+        ))
+        .header(header::USER_AGENT, USER_AGENT)
+        .send()
+        .await
+        .ok()?
+        .json::<DocsStatus>()
+        .await
+        .ok()?;
+
+    Some(status.doc_status)
+}
+
+/// Turn a piece of rendered HTML into plain text on one line.  
+fn html_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    let text = text
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&");
+
+    // Tags leave spaces before punctuation, like in `<code>serde</code>.`
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace(" .", ".")
+        .replace(" ,", ",")
+}
+
+/// Cut `text` at the last whole word within `max_len` characters.  
+fn truncate_words(text: &str, max_len: usize) -> String {
+    let end = match text.char_indices().nth(max_len) {
+        Some((end, _)) => end,
+        None => return text.to_string(),
+    };
+
+    let cut = text[..end].rfind(' ').unwrap_or(end);
+    format!(
+        "{}…",
+        text[..cut].trim_end_matches(|c: char| c.is_ascii_punctuation())
+    )
+}
+
+/// The first paragraph of text in the README of the crate's latest version, for crates whose
+/// description says little.  
+///
+/// Paragraphs without text, like a row of badges, are skipped.  
+async fn readme_excerpt(http: &HttpClient, krate: &Crate) -> Option<String> {
+    let description = krate.description.trim();
+    if description.chars().count() >= SPARSE_DESCRIPTION_LEN {
+        return None;
+    }
+
+    // crates.io redirects this to the README it rendered when the version was published
+    let readme = http
+        .get(format!(
+            "https://crates.io/api/v1/crates/{}/{}/readme",
+            krate.id,
+            krate.version()
+        ))
+        .header(header::USER_AGENT, USER_AGENT)
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+
+    readme
+        .split("<p")
+        .skip(1)
+        // Only `<p>` and `<p ...>`, not tags like `<pre>`
+        .filter(|rest| rest.starts_with('>') || rest.starts_with(' '))
+        .filter_map(|rest| rest.split_once('>'))
+        .map(|(_, rest)| html_text(rest.split("</p>").next().unwrap_or(rest)))
+        .find(|text| !text.is_empty() && text != description)
+        .map(|text| truncate_words(&text, README_EXCERPT_LEN))
+}
+
+/// Find a few popular crates sharing the first keyword of `krate`.  
+///
+/// Returns an empty list if the crate has no keywords or crates.io could not be reached.
+async fn related(http: &HttpClient, krate: &Crate) -> Vec<String> {
+    let keyword = match krate
+        .keywords
+        .as_ref()
+        .and_then(|keywords| keywords.first())
+    {
+        Some(keyword) => keyword,
+        None => return Vec::new(),
+    };
+
+    let per_page = (MAX_RELATED_CRATES + 1).to_string();
+    let crates = get_crates(
+        http,
+        &[
+            ("keyword", keyword),
+            ("sort", "downloads"),
+            ("per_page", &per_page),
+        ],
+    )
+    .await
+    .unwrap_or_default();
+
+    crates
+        .into_iter()
+        .map(|related| related.name)
+        .filter(|name| *name != krate.name)
+        .take(MAX_RELATED_CRATES)
+        .collect()
+}
+
+/// Split a stable version like `1.2.3` into its numbers, ignoring pre-releases.  
+fn parse_version(num: &str) -> Option<(u64, u64, u64)> {
+    let mut parts = num.split('+').next()?.split('.');
+    let version = (
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+        parts.next()?.parse().ok()?,
+    );
+
+    match parts.next() {
+        Some(_) => None,
+        None => Some(version),
+    }
+}
+
+async fn get_versions(http: &HttpClient, krate: &Crate) -> Result<Vec<Version>, Error> {
+    let versions = http
+        .get(format!(
+            "https://crates.io/api/v1/crates/{}/versions",
+            krate.id
+        ))
+        .header(header::USER_AGENT, USER_AGENT)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Versions>()
+        .await?;
+
+    Ok(versions.versions)
+}
+
+/// The latest release of each semver compatible line, like `1.x` or `0.12.x`, newest first.  
+///
+/// Only returned when more than one line has been released.  
+fn latest_per_major(versions: &[Version]) -> Vec<(String, String)> {
+    let mut latest: Vec<(String, (u64, u64, u64), &str)> = Vec::new();
+    for version in versions.iter().filter(|version| !version.yanked) {
+        let parsed = match parse_version(&version.num) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+
+        let line = match parsed {
+            (0, 0, patch) => format!("0.0.{}", patch),
+            (0, minor, _) => format!("0.{}.x", minor),
+            (major, _, _) => format!("{}.x", major),
+        };
+
+        match latest.iter_mut().find(|(name, _, _)| *name == line) {
+            Some(entry) if entry.1 < parsed => *entry = (line, parsed, &version.num),
+            Some(_) => {}
+            None => latest.push((line, parsed, &version.num)),
+        }
+    }
+
+    if latest.len() < 2 {
+        return Vec::new();
+    }
+
+    latest.sort_by_key(|(_, parsed, _)| std::cmp::Reverse(*parsed));
+    latest
+        .into_iter()
+        .take(MAX_MAJOR_VERSIONS)
+        .map(|(line, _, num)| (line, num.to_string()))
+        .collect()
+}
+
+/// A field of the version shown for the crate, like its license `MIT OR Apache-2.0`.  
+fn version_field<F>(versions: &[Version], krate: &Crate, field: F) -> Option<String>
+where
+    F: Fn(&Version) -> &Option<String>,
+{
+    versions
+        .iter()
+        .find(|version| version.num == krate.version())
+        .and_then(|version| field(version).as_ref())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Guess whether a crate can be used without `std`, from its keywords, categories and features.  
+///
+/// This is a heuristic: the keywords are up to the author, and a crate with a `std` feature
+/// might not build without it.  
+fn no_std(versions: &[Version], krate: &Crate) -> Option<&'static str> {
+    let tagged = krate
+        .keywords
+        .iter()
+        .chain(&krate.categories)
+        .flatten()
+        .any(|tag| {
+            matches!(&tag[..], "no_std" | "no-std" | "nostd") || tag.starts_with("no-std::")
+        });
+    if tagged {
+        return Some("yes");
+    }
+
+    // `std` being a default feature means it can be turned off
+    let features = &versions
+        .iter()
+        .find(|version| version.num == krate.version())?
+        .features;
+    let std_is_default = features.contains_key("std")
+        && features
+            .get("default")
+            .is_some_and(|default| default.iter().any(|feature| feature == "std"));
+
+    std_is_default.then_some("likely")
+}
+
+/// Well-known crates that are deprecated or no longer the usual choice, with what is used
+/// instead.  
+///
+/// Only crates whose own documentation or maintainers point to the successor belong here, so the
+/// note isn't an opinion of the bot.  
+const SUPERSEDED_CRATES: [(&str, &str); 4] = [
+    ("rustc-serialize", "`serde`"),
+    (
+        "error-chain",
+        "`thiserror` for libraries and `anyhow` for applications",
+    ),
+    (
+        "failure",
+        "`thiserror` for libraries and `anyhow` for applications",
+    ),
+    ("tempdir", "`tempfile`"),
+];
+
+/// What a deprecated or outdated crate has largely been superseded by.  
+fn superseded_by(krate: &Crate) -> Option<&'static str> {
+    SUPERSEDED_CRATES
+        .iter()
+        .find(|(name, _)| *name == krate.id)
+        .map(|(_, successor)| *successor)
+}
+
+/// The features the current version enables by default, like `default = ["json"]`.  
+fn default_features(versions: &[Version], krate: &Crate) -> Option<String> {
+    let features = &versions
+        .iter()
+        .find(|version| version.num == krate.version())?
+        .features;
+
+    let default = features.get("default").map(Vec::as_slice).unwrap_or(&[]);
+    let mut list = default
+        .iter()
+        .take(MAX_DEFAULT_FEATURES)
+        .map(|feature| format!("\"{}\"", feature))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if default.len() > MAX_DEFAULT_FEATURES {
+        list += &format!(", … {} more", default.len() - MAX_DEFAULT_FEATURES);
+    }
+
+    Some(format!("default = [{}]", list))
+}
+
+/// Link to the `CHANGELOG.md` at the root of the crate's repository, if it is on GitHub or
+/// GitLab and the file exists.  
+async fn changelog(http: &HttpClient, krate: &Crate) -> Option<String> {
+    let repository = krate.repository.as_ref()?.trim_end_matches('/');
+    let repository = repository.strip_suffix(".git").unwrap_or(repository);
+
+    // Only links to the root of a repository, like `https://github.com/owner/repo`
+    let (host, path) = repository
+        .strip_prefix("https://")
+        .or_else(|| repository.strip_prefix("http://"))?
+        .split_once('/')?;
+    if path.split('/').count() != 2 {
+        return None;
+    }
+
+    // `HEAD` is the default branch, whatever it is called
+    let url = match host {
+        "github.com" | "www.github.com" => {
+            format!("https://github.com/{}/blob/HEAD/CHANGELOG.md", path)
+        }
+        "gitlab.com" | "www.gitlab.com" => {
+            format!("https://gitlab.com/{}/-/blob/HEAD/CHANGELOG.md", path)
+        }
+        _ => return None,
+    };
+
+    let response = http
+        .head(&url)
+        .header(header::USER_AGENT, USER_AGENT)
+        .send()
+        .await
+        .ok()?;
+
+    response.status().is_success().then_some(url)
+}
+
+/// Everything shown about a crate besides its own metadata.  
+struct CrateDetails {
+    docs_built: Option<bool>,
+    advisories: Vec<String>,
+    related: Vec<String>,
+    latest: Vec<(String, String)>,
+    license: Option<String>,
+    edition: Option<String>,
+    rust_version: Option<String>,
+    no_std: Option<&'static str>,
+    default_features: Option<String>,
+    keywords: Vec<String>,
+    categories: Vec<String>,
+    changelog: Option<String>,
+    readme: Option<String>,
+    /// How many crates matched the search the crate was the best result of.  
+    matches: Option<u64>,
+}
+
+impl CrateDetails {
+    /// Look up the details of a crate, leaving out any that can't be retrieved in time.  
+    async fn fetch(cx: &Context, http: &HttpClient, krate: &Crate) -> Self {
+        // Search results leave out the keywords and categories, looking the crate up has them
+        let full = match krate.keywords {
+            Some(_) => None,
+            None => {
+                within_timeout("the keywords", krate, async {
+                    get_exact_crate(http, &krate.id).await.unwrap_or_else(|e| {
+                        info!("unable to look up `{}`: {}", krate.name, e);
+                        None
+                    })
+                })
+                .await
+            }
+        };
+        let krate = full.as_ref().unwrap_or(krate);
+
+        let versions = async {
+            get_versions(http, krate).await.unwrap_or_else(|e| {
+                info!("unable to look up the versions of `{}`: {}", krate.name, e);
+                Vec::new()
+            })
+        };
+
+        let (versions, docs_built, advisories, related, changelog, readme) = futures::join!(
+            within_timeout("the versions", krate, versions),
+            within_timeout("the docs.rs build", krate, docs_built(http, krate)),
+            within_timeout("the advisories", krate, advisories(cx, http, krate)),
+            within_timeout("related crates", krate, related(http, krate)),
+            within_timeout("the changelog", krate, changelog(http, krate)),
+            within_timeout("the README", krate, readme_excerpt(http, krate)),
+        );
+
+        Self {
+            docs_built,
+            advisories,
+            related,
+            latest: latest_per_major(&versions),
+            license: version_field(&versions, krate, |version| &version.license),
+            edition: version_field(&versions, krate, |version| &version.edition),
+            rust_version: version_field(&versions, krate, |version| &version.rust_version),
+            no_std: no_std(&versions, krate),
+            default_features: default_features(&versions, krate),
+            keywords: krate.keywords.clone().unwrap_or_default(),
+            categories: krate.categories.clone().unwrap_or_default(),
+            changelog,
+            readme,
+            matches: None,
+        }
+    }
+}
+
+/// Wait at most `DETAILS_TIMEOUT` for one of the details of a crate, leaving it out otherwise.  
+async fn within_timeout<T, F>(what: &str, krate: &Crate, lookup: F) -> T
+where
+    T: Default,
+    F: Future<Output = T>,
+{
+    timeout(DETAILS_TIMEOUT, lookup).await.unwrap_or_else(|_| {
+        info!("timed out looking up {} of `{}`", what, krate.name);
+        T::default()
+    })
+}
+
+/// Join the first `MAX_CRATE_TAGS` keywords or categories, noting how many were left out.  
+fn format_tags<F>(tags: &[String], format: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let mut text = tags
+        .iter()
+        .take(MAX_CRATE_TAGS)
+        .map(|tag| format(tag))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if tags.len() > MAX_CRATE_TAGS {
+        text += &format!(" and {} more", tags.len() - MAX_CRATE_TAGS);
+    }
+    text
+}
+
+fn format_latest_per_major(latest: &[(String, String)]) -> String {
+    latest
+        .iter()
+        .map(|(line, num)| format!("{}: {}", line, num))
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+async fn query_advisories(http: &HttpClient, krate: &Crate) -> Result<Vec<String>, Error> {
+    let query = VulnerabilityQuery {
+        package: Package {
+            name: &krate.name,
+            ecosystem: "crates.io",
+        },
+        version: krate.version(),
+    };
+
+    let vulnerabilities = http
+        .post("https://api.osv.dev/v1/query")
+        .header(header::USER_AGENT, USER_AGENT)
+        .json(&query)
+        .send()
+        .await?
+        .json::<Vulnerabilities>()
+        .await?;
+
+    Ok(vulnerabilities
+        .vulns
+        .into_iter()
+        .map(|vuln| vuln.id)
+        .collect())
+}
+
+/// Look up the RustSec advisories affecting a version of a crate using the OSV database.  
+///
+/// Returns an empty list if the database could not be reached.
+async fn advisories(cx: &Context, http: &HttpClient, krate: &Crate) -> Vec<String> {
+    let key = (krate.name.clone(), krate.version().to_string());
+
+    {
+        let data = cx.data.read().await;
+        let cache = data.get::<AdvisoryCache>().unwrap();
+        if let Some(ids) = cache.get(&key) {
+            return ids.clone();
+        }
+    }
+
+    match query_advisories(http, krate).await {
+        Ok(ids) => {
+            let mut data = cx.data.write().await;
+            let cache = data.get_mut::<AdvisoryCache>().unwrap();
+            cache.insert(key, ids.clone());
+            ids
+        }
+        Err(e) => {
+            info!("unable to look up advisories for `{}`: {}", krate.name, e);
+            Vec::new()
+        }
+    }
+}
+
+/// The page of an advisory returned by OSV, which also has ids from other databases, like
+/// `GHSA-...` for GitHub's.  
+fn advisory_url(id: &str) -> String {
+    if id.starts_with("RUSTSEC-") {
+        format!("https://rustsec.org/advisories/{}", id)
+    } else {
+        format!("https://osv.dev/vulnerability/{}", id)
+    }
+}
+
+/// Forget the cached advisories so newly published ones are picked up.  
+pub async fn clear_advisory_cache(cx: &Context) -> Result<(), Error> {
+    let mut data = cx.data.write().await;
+    let cache = data.get_mut::<AdvisoryCache>().unwrap();
+
+    info!("Clearing advisory cache");
+    cache.clear();
+    Ok(())
+}
+
+/// The search result count and the caution note, shown below the rest of the crate.  
+fn footer(krate: &Crate, details: &CrateDetails) -> String {
+    let mut parts = Vec::new();
+
+    if let Some(matches) = details.matches.filter(|matches| *matches > 1) {
+        parts.push(format!(
+            "1 of {} results, use ?crate exact {{name}} for another",
+            matches
+        ));
+    }
+
+    if let Some(caution) = krate.caution() {
+        parts.push(format!("⚠️ {}", caution));
+    }
+
+    parts.join(" · ")
+}
+
+/// The edition of the crate along with its minimum supported rust version, like
+/// `2021 (rust 1.70+)`.  
+fn edition(details: &CrateDetails) -> Option<String> {
+    let edition = details.edition.as_ref()?;
+
+    Some(match &details.rust_version {
+        Some(rust_version) => format!("{} (rust {}+)", edition, rust_version),
+        None => edition.clone(),
+    })
+}
+
+/// The description of the crate, followed by the start of its README if it is short.  
+fn description(krate: &Crate, details: &CrateDetails) -> String {
+    match &details.readme {
+        Some(readme) => format!("{}\n\n{}", krate.description(), readme),
+        None => krate.description().to_string(),
+    }
+}
+
+fn crate_embed<'a>(
+    e: &'a mut CreateEmbed,
+    krate: &Crate,
+    details: &CrateDetails,
+    lib_rs: bool,
+) -> &'a mut CreateEmbed {
+    e.title(&krate.name)
+        .url(format!("https://crates.io/crates/{}", krate.id))
+        .description(description(krate, details))
+        .field("version", krate.version(), true)
+        .field("downloads", &krate.downloads, true)
+        .timestamp(krate.updated.as_str());
+
+    if let Some(updated) = krate.updated_at() {
+        let days = Utc::now().signed_duration_since(updated).num_days();
+        let color = if days < FRESH_DAYS {
+            Colour::DARK_GREEN
+        } else if days < STALE_DAYS {
+            Colour::GOLD
+        } else {
+            Colour::RED
+        };
+
+        e.field("updated", time_ago(updated), true).color(color);
+    }
+
+    if let Some(docs_built) = details.docs_built {
+        e.field("docs.rs", if docs_built { "✅" } else { "❌" }, true);
+    }
+
+    if !details.latest.is_empty() {
+        e.field(
+            "latest per major",
+            format_latest_per_major(&details.latest),
+            false,
+        );
+    }
+
+    if !details.advisories.is_empty() {
+        let links = details
+            .advisories
+            .iter()
+            .map(|id| format!("[{}]({})", id, advisory_url(id)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        e.field("⚠️ security advisories", links, false);
+    }
+
+    if let Some(successor) = superseded_by(krate) {
+        e.field(
+            "⚠️ superseded",
+            format!("largely superseded by {}", successor),
+            false,
+        );
+    }
+
+    e.field(
+        "license",
+        details.license.as_deref().unwrap_or("unknown"),
+        true,
+    );
+
+    if let Some(edition) = edition(details) {
+        e.field("edition", edition, true);
+    }
+
+    if let Some(no_std) = details.no_std {
+        e.field("no_std", no_std, true);
+    }
+
+    if let Some(default_features) = &details.default_features {
+        e.field("default features", format!("`{}`", default_features), false);
+    }
+
+    if !details.keywords.is_empty() {
+        e.field(
+            "keywords",
+            format_tags(&details.keywords, str::to_string),
+            false,
+        );
+    }
+
+    if !details.categories.is_empty() {
+        let links = format_tags(&details.categories, |slug| {
+            format!("[{0}](https://crates.io/categories/{0})", slug)
+        });
+        e.field("categories", links, false);
+    }
+
+    if let Some(repository) = &krate.repository {
+        e.field("repository", format!("[link]({})", repository), true);
+    }
+
+    if let Some(changelog) = &details.changelog {
+        e.field("changelog", format!("[link]({})", changelog), true);
+    }
+
+    if !details.related.is_empty() {
+        let links = details
+            .related
+            .iter()
+            .map(|name| format!("[{0}](https://crates.io/crates/{0})", name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        e.field("see also", links, false);
+    }
+
+    let footer = footer(krate, details);
+    if !footer.is_empty() {
+        e.footer(|f| f.text(footer));
+    }
+
+    if lib_rs {
+        e.field(
+            "lib.rs",
+            format!("[{0}](https://lib.rs/crates/{0})", krate.id),
+            true,
+        );
+    }
+
+    e
 }
-#[derive(Debug, Deserialize)]
-struct Crate {
-    id: String,
-    name: String,
-    newest_version: String,
-    max_stable_version: Option<String>,
-    #[serde(rename = "updated_at")]
-    updated: String,
-    downloads: u64,
-    #[serde(default)]
-    description: String,
-    documentation: Option<String>,
+
+/// The same information as `crate_embed`, for channels where embeds are hard to read.  
+fn crate_text(krate: &Crate, details: &CrateDetails, lib_rs: bool) -> String {
+    let mut text = format!(
+        "**{}** <https://crates.io/crates/{}>\n",
+        krate.name, krate.id
+    );
+
+    text += &format!("{}\n", description(krate, details));
+
+    text += &format!(
+        "version: {} | downloads: {}",
+        krate.version(),
+        krate.downloads
+    );
+
+    if let Some(updated) = krate.updated_at() {
+        text += &format!(" | updated: {}", time_ago(updated));
+    }
+
+    if let Some(docs_built) = details.docs_built {
+        text += &format!(" | docs.rs: {}", if docs_built { "✅" } else { "❌" });
+    }
+
+    text += &format!(
+        " | license: {}",
+        details.license.as_deref().unwrap_or("unknown")
+    );
+
+    if let Some(edition) = edition(details) {
+        text += &format!(" | edition: {}", edition);
+    }
+
+    if let Some(no_std) = details.no_std {
+        text += &format!(" | no_std: {}", no_std);
+    }
+
+    if let Some(default_features) = &details.default_features {
+        text += &format!("\ndefault features: `{}`", default_features);
+    }
+
+    if !details.latest.is_empty() {
+        text += &format!(
+            "\nlatest per major: {}",
+            format_latest_per_major(&details.latest)
+        );
+    }
+
+    if !details.advisories.is_empty() {
+        text += &format!(
+            "\n⚠️ security advisories: {}",
+            details.advisories.join(", ")
+        );
+    }
+
+    if let Some(successor) = superseded_by(krate) {
+        text += &format!("\n⚠️ largely superseded by {}", successor);
+    }
+
+    if !details.keywords.is_empty() {
+        text += &format!(
+            "\nkeywords: {}",
+            format_tags(&details.keywords, str::to_string)
+        );
+    }
+
+    if !details.categories.is_empty() {
+        text += &format!(
+            "\ncategories: {}",
+            format_tags(&details.categories, str::to_string)
+        );
+    }
+
+    if let Some(repository) = &krate.repository {
+        text += &format!("\nrepository: <{}>", repository);
+    }
+
+    if let Some(changelog) = &details.changelog {
+        text += &format!("\nchangelog: <{}>", changelog);
+    }
+
+    if !details.related.is_empty() {
+        text += &format!("\nsee also: {}", details.related.join(", "));
+    }
+
+    if lib_rs {
+        text += &format!("\nlib.rs: <https://lib.rs/crates/{}>", krate.id);
+    }
+
+    let footer = footer(krate, details);
+    if !footer.is_empty() {
+        text += &format!("\n*{}*", footer);
+    }
+
+    truncate_lines(&text, CRATE_TEXT_LEN)
+}
+
+/// Cut `text` at the last whole line within `max_len` characters, so links aren't broken
+/// up.  A single line that is too long is cut at a word instead.  
+fn truncate_lines(text: &str, max_len: usize) -> String {
+    // Leave room for the `…` marking the cut
+    let end = match text.char_indices().nth(max_len - 1) {
+        Some((end, _)) if text.chars().count() > max_len => end,
+        _ => return text.to_string(),
+    };
+
+    match text[..end].rfind('\n') {
+        Some(cut) => format!("{}\n…", &text[..cut]),
+        None => truncate_words(text, max_len - 1),
+    }
+}
+
+async fn send_crate_list(args: Arc<Args>, title: &str, crates: Vec<Crate>) -> Result<(), Error> {
+    if crates.is_empty() {
+        api::send_reply(args.clone(), "No crates found.").await?;
+    } else {
+        let list = crates.iter().fold(String::new(), |list, krate| {
+            list + &format!("{:<30}{:>12} downloads\n", krate.name, krate.downloads)
+        });
+
+        api::send_reply(args.clone(), &format!("{}:\n```\n{}```", title, list)).await?;
+    }
+
+    Ok(())
+}
+
+/// List the most downloaded crates tagged with a keyword.  
+pub async fn keyword_search(args: Arc<Args>) -> Result<(), Error> {
+    let keyword = args
+        .params
+        .get("keyword")
+        .ok_or("Unable to retrieve param: keyword")?;
+
+    info!("searching for crates with keyword `{}`", keyword);
+
+    let crates = get_crates(
+        &args.http,
+        &[
+            ("keyword", keyword),
+            ("sort", "downloads"),
+            ("per_page", CRATE_LIST_SIZE),
+        ],
+    )
+    .await?;
+
+    send_crate_list(
+        args.clone(),
+        &format!("Top crates for keyword `{}`", keyword),
+        crates,
+    )
+    .await
+}
+
+/// List the most downloaded crates in a category.  
+pub async fn category_search(args: Arc<Args>) -> Result<(), Error> {
+    let category = args
+        .params
+        .get("category")
+        .ok_or("Unable to retrieve param: category")?;
+
+    info!("searching for crates in category `{}`", category);
+
+    let crates = get_crates(
+        &args.http,
+        &[
+            ("category", category),
+            ("sort", "downloads"),
+            ("per_page", CRATE_LIST_SIZE),
+        ],
+    )
+    .await?;
+
+    send_crate_list(
+        args.clone(),
+        &format!("Top crates in category `{}`", category),
+        crates,
+    )
+    .await
+}
+
+async fn send_crate(
+    args: Arc<Args>,
+    krate: Option<Crate>,
+    matches: Option<u64>,
+    lib_rs: bool,
+    plain: bool,
+) -> Result<(), Error> {
+    if let Some(krate) = krate {
+        let details = CrateDetails {
+            matches,
+            ..CrateDetails::fetch(&args.cx, &args.http, &krate).await
+        };
+
+        if plain {
+            let text = crate_text(&krate, &details, lib_rs);
+            api::send_reply(args.clone(), &text).await?;
+            return Ok(());
+        }
+
+        api::send_or_edit_embed(args.clone(), |e| crate_embed(e, &krate, &details, lib_rs)).await?;
+    } else {
+        let message = "No crates found.";
+        api::send_reply(args.clone(), message).await?;
+    }
+
+    Ok(())
+}
+
+/// Show the best match for a search, or the closest names when it's unclear which was meant.  
+async fn send_search(args: Arc<Args>, query: &str, lib_rs: bool, plain: bool) -> Result<(), Error> {
+    if let Some(message) = std_item_message(query) {
+        api::send_reply(args.clone(), &message).await?;
+        return Ok(());
+    }
+
+    let _typing = api::start_typing(&args);
+    let search = search_crate(&args.http, query).await?;
+
+    if !search.suggestions.is_empty() {
+        let message = suggestions_message(query, &search.suggestions);
+        api::send_reply(args.clone(), &message).await?;
+        return Ok(());
+    }
+
+    send_crate(args.clone(), search.krate, search.matches, lib_rs, plain).await
 }
 
-async fn get_crate(args: Arc<Args>) -> Result<Option<Crate>, Error> {
+/// Show the best match for a search, also linking to lib.rs when `LibRsLinks` is enabled.  
+pub async fn search(args: Arc<Args>) -> Result<(), Error> {
+    let lib_rs = lib_rs_links(&args.cx).await;
     let query = args
         .params
         .get("query")
         .ok_or("Unable to retrieve param: query")?;
 
-    info!("searching for crate `{}`", query);
+    send_search(args.clone(), query, lib_rs, false).await
+}
 
-    let crate_list = args
-        .http
-        .get("https://crates.io/api/v1/crates")
+/// The `sort` values `?crate` accepts, with the name crates.io uses for them.  
+const SORT_ORDERS: [(&str, &str); 5] = [
+    ("relevance", "relevance"),
+    ("downloads", "downloads"),
+    ("recent-downloads", "recent-downloads"),
+    ("newly-added", "new"),
+    ("recently-updated", "recent-updates"),
+];
+
+/// Show the first match for a search in the given `sort` order.  
+pub async fn sorted_search(args: Arc<Args>) -> Result<(), Error> {
+    let lib_rs = lib_rs_links(&args.cx).await;
+    let sort = match args.params.get("sort") {
+        Some(sort) => sort,
+        None => return search(args).await,
+    };
+    let query = args
+        .params
+        .get("query")
+        .ok_or("Unable to retrieve param: query")?;
+
+    let order = match SORT_ORDERS.iter().find(|(name, _)| name == sort) {
+        Some((_, order)) => order,
+        None => {
+            let names = SORT_ORDERS
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ");
+            let message = format!("Unknown sort order `{}`, use one of: {}", sort, names);
+            api::send_reply(args.clone(), &message).await?;
+            return Ok(());
+        }
+    };
+
+    info!("searching for crate `{}` sorted by {}", query, order);
+    let _typing = api::start_typing(&args);
+    let Crates { crates, meta } = get_crate_list(
+        &args.http,
+        &[("q", query), ("sort", order), ("per_page", "1")],
+    )
+    .await?;
+    send_crate(
+        args.clone(),
+        crates.into_iter().next(),
+        Some(meta.total),
+        lib_rs,
+        false,
+    )
+    .await
+}
+
+/// Show the best match for a search as plain text instead of an embed.  
+pub async fn text_search(args: Arc<Args>) -> Result<(), Error> {
+    let lib_rs = lib_rs_links(&args.cx).await;
+    let query = args
+        .params
+        .get("query")
+        .ok_or("Unable to retrieve param: query")?;
+
+    send_search(args.clone(), query, lib_rs, true).await
+}
+
+/// Show the crate with exactly the given name, without searching.  
+pub async fn exact_search(args: Arc<Args>) -> Result<(), Error> {
+    let lib_rs = lib_rs_links(&args.cx).await;
+    let name = args
+        .params
+        .get("name")
+        .ok_or("Unable to retrieve param: name")?;
+
+    let _typing = api::start_typing(&args);
+    let krate = get_exact_crate(&args.http, name).await?;
+    send_crate(args.clone(), krate, None, lib_rs, false).await
+}
+
+async fn get_dependencies(http: &HttpClient, krate: &Crate) -> Result<Vec<Dependency>, Error> {
+    let dependencies = http
+        .get(format!(
+            "https://crates.io/api/v1/crates/{}/{}/dependencies",
+            krate.id,
+            krate.version()
+        ))
         .header(header::USER_AGENT, USER_AGENT)
-        .query(&[("q", query)])
         .send()
         .await?
-        .json::<Crates>()
+        .error_for_status()?
+        .json::<Dependencies>()
         .await?;
 
-    Ok(crate_list.crates.into_iter().next())
+    Ok(dependencies.dependencies)
 }
 
-pub async fn search(args: Arc<Args>) -> Result<(), Error> {
-    if let Some(krate) = get_crate(args.clone()).await? {
-        args.msg
-            .channel_id
-            .send_message(&args.cx, |m| {
-                m.embed(|e| {
-                    e.title(&krate.name)
-                        .url(format!("https://crates.io/crates/{}", krate.id))
-                        .description(&krate.description)
-                        .field(
-                            "version",
-                            krate
-                                .max_stable_version
-                                .as_ref()
-                                .unwrap_or(&krate.newest_version),
-                            true,
-                        )
-                        .field("downloads", &krate.downloads, true)
-                        .timestamp(krate.updated.as_str())
+/// List the direct dependencies of the latest version of a crate, grouped by kind.  
+pub async fn dependencies(args: Arc<Args>) -> Result<(), Error> {
+    let name = args
+        .params
+        .get("name")
+        .ok_or("Unable to retrieve param: name")?;
+
+    let krate = match get_crate(&args.http, name).await? {
+        Some(krate) => krate,
+        None => {
+            api::send_reply(args.clone(), "No crates found.").await?;
+            return Ok(());
+        }
+    };
+
+    info!("listing dependencies of `{}`", krate.name);
+    let dependencies = get_dependencies(&args.http, &krate).await?;
+
+    let mut message = format!("Dependencies of `{}` {}:", krate.name, krate.version());
+
+    if dependencies.is_empty() {
+        message += " none";
+    }
+
+    for kind in &["normal", "build", "dev"] {
+        let deps = dependencies
+            .iter()
+            .filter(|dep| dep.kind == *kind)
+            .collect::<Vec<_>>();
+
+        if deps.is_empty() {
+            continue;
+        }
+
+        let mut list =
+            deps.iter()
+                .take(MAX_DEPENDENCIES_PER_KIND)
+                .fold(String::new(), |list, dep| {
+                    let optional = if dep.optional { " (optional)" } else { "" };
+                    list + &format!("{:<30}{}{}\n", dep.crate_id, dep.req, optional)
                 });
 
-                m
+        if deps.len() > MAX_DEPENDENCIES_PER_KIND {
+            list += &format!("... and {} more\n", deps.len() - MAX_DEPENDENCIES_PER_KIND);
+        }
+
+        message += &format!("\n**{}**```\n{}```", kind, list);
+    }
+
+    api::send_reply(args.clone(), &message).await?;
+    Ok(())
+}
+
+/// List the feature names in `a` that aren't in `b`, sorted.  
+fn missing_features<'a>(a: &'a Version, b: &Version) -> Vec<&'a str> {
+    let mut missing = a
+        .features
+        .keys()
+        .filter(|feature| !b.features.contains_key(*feature))
+        .map(|feature| &feature[..])
+        .collect::<Vec<_>>();
+    missing.sort_unstable();
+    missing
+}
+
+/// Link to the diff between two versions of a crate, with what changed in their metadata.  
+pub async fn changes(args: Arc<Args>) -> Result<(), Error> {
+    let name = args
+        .params
+        .get("name")
+        .ok_or("Unable to retrieve param: name")?;
+    let from = args
+        .params
+        .get("from")
+        .ok_or("Unable to retrieve param: from")?;
+    let to = args
+        .params
+        .get("to")
+        .ok_or("Unable to retrieve param: to")?;
+
+    let _typing = api::start_typing(&args);
+    let krate = match get_exact_crate(&args.http, name).await? {
+        Some(krate) => krate,
+        None => {
+            api::send_reply(args.clone(), "No crates found.").await?;
+            return Ok(());
+        }
+    };
+
+    info!("comparing `{}` {} to {}", krate.name, from, to);
+    let versions = get_versions(&args.http, &krate).await?;
+    let find = |num: &str| {
+        let num = num.trim_start_matches('v');
+        versions.iter().find(|version| version.num == num)
+    };
+
+    let (old, new) = match (find(from), find(to)) {
+        (Some(old), Some(new)) => (old, new),
+        (old, _) => {
+            let missing = if old.is_none() { from } else { to };
+            let message = format!(
+                "`{}` has no version {}, the latest is {}",
+                krate.name,
+                missing,
+                krate.version()
+            );
+            api::send_reply(args.clone(), &message).await?;
+            return Ok(());
+        }
+    };
+
+    let mut message = format!(
+        "**{}** {} → {}: <https://diff.rs/{}/{}/{}>",
+        krate.name, old.num, new.num, krate.id, old.num, new.num
+    );
+
+    let added = missing_features(new, old);
+    if !added.is_empty() {
+        message += &format!("\nnew features: {}", added.join(", "));
+    }
+
+    let removed = missing_features(old, new);
+    if !removed.is_empty() {
+        message += &format!("\nremoved features: {}", removed.join(", "));
+    }
+
+    if old.rust_version != new.rust_version {
+        let msrv = |version: &Version| {
+            version
+                .rust_version
+                .clone()
+                .unwrap_or_else(|| "unset".into())
+        };
+        message += &format!("\nMSRV: {} → {}", msrv(old), msrv(new));
+    }
+
+    if old.license != new.license {
+        let license =
+            |version: &Version| version.license.clone().unwrap_or_else(|| "unknown".into());
+        message += &format!("\nlicense: {} → {}", license(old), license(new));
+    }
+
+    if new.yanked {
+        message += &format!("\n⚠️ {} has been yanked", new.num);
+    }
+
+    api::send_reply(args.clone(), &message).await?;
+    Ok(())
+}
+
+/// Handle the `/crate` slash command.  
+pub async fn slash_search(
+    cx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    http: &HttpClient,
+) -> Result<(), Error> {
+    let lib_rs = lib_rs_links(cx).await;
+    let query = slash::option(interaction, "query").ok_or("Unable to retrieve option: query")?;
+
+    if let Some(message) = std_item_message(query) {
+        slash::respond(cx, interaction, &message).await?;
+        return Ok(());
+    }
+
+    let search = search_crate(http, query).await?;
+
+    if !search.suggestions.is_empty() {
+        let message = suggestions_message(query, &search.suggestions);
+        slash::respond(cx, interaction, &message).await?;
+    } else if let Some(krate) = search.krate {
+        let details = CrateDetails {
+            matches: search.matches,
+            ..CrateDetails::fetch(cx, http, &krate).await
+        };
+        interaction
+            .edit_original_interaction_response(cx, |r| {
+                r.embed(|e| crate_embed(e, &krate, &details, lib_rs))
             })
             .await?;
     } else {
-        let message = "No crates found.";
-        api::send_reply(args.clone(), message).await?;
+        slash::respond(cx, interaction, "No crates found.").await?;
     }
 
     Ok(())
@@ -90,31 +1603,102 @@ fn rustc_crate(crate_name: &str) -> Option<&str> {
     }
 }
 
-pub async fn doc_search(args: Arc<Args>) -> Result<(), Error> {
-    let query = args
-        .params
-        .get("query")
-        .ok_or("Unable to retrieve param: query")?;
+/// Well-known items of the standard library, which crates.io searches would match to unrelated
+/// crates.  
+const STD_ITEMS: [&str; 24] = [
+    "Vec",
+    "String",
+    "HashMap",
+    "HashSet",
+    "BTreeMap",
+    "BTreeSet",
+    "VecDeque",
+    "BinaryHeap",
+    "LinkedList",
+    "Option",
+    "Result",
+    "Box",
+    "Rc",
+    "Arc",
+    "Cell",
+    "RefCell",
+    "Mutex",
+    "RwLock",
+    "Cow",
+    "Path",
+    "PathBuf",
+    "Duration",
+    "Instant",
+    "Iterator",
+];
+
+/// Point to the std docs instead of searching crates.io when `query` names a std item.  
+fn std_item_message(query: &str) -> Option<String> {
+    let item = query.trim();
+    if !STD_ITEMS.contains(&item) {
+        return None;
+    }
+
+    let url = rustc_crate("std")?;
+    Some(format!(
+        "`{}` is part of the standard library, not a crate: <{}?search={}>",
+        item, url, item
+    ))
+}
+
+/// Whether an error means crates.io couldn't be reached, rather than that the lookup failed.  
+fn is_unavailable(error: &Error) -> bool {
+    match error.downcast_ref::<reqwest::Error>() {
+        Some(e) => {
+            e.is_connect()
+                || e.is_timeout()
+                || e.status().is_some_and(|status| status.is_server_error())
+        }
+        None => false,
+    }
+}
 
+async fn doc_url(http: &HttpClient, query: &str) -> Result<Option<String>, Error> {
     let mut query_iter = query.splitn(2, "::");
     let crate_name = query_iter.next().unwrap();
+    let mut note = "";
 
     let doc_url = if let Some(rustc_crate) = rustc_crate(crate_name) {
         Some(rustc_crate.to_string())
-    } else if let Some(krate) = get_crate(args.clone()).await? {
-        let name = krate.name;
-        krate
-            .documentation
-            .or_else(|| Some(format!("https://docs.rs/{}", name)))
     } else {
-        None
+        match get_crate(http, query).await {
+            Ok(Some(krate)) => {
+                let name = krate.name;
+                krate
+                    .documentation
+                    .or_else(|| Some(format!("https://docs.rs/{}", name)))
+            }
+            Ok(None) => None,
+            // Guess the docs.rs link so the most used lookup keeps working during an outage
+            Err(e) if is_unavailable(&e) => {
+                info!("crates.io is unavailable, linking to docs.rs: {}", e);
+                note = "\n*crates.io is unavailable, so this link is a best guess.*";
+                Some(format!("https://docs.rs/{}", crate_name))
+            }
+            Err(e) => return Err(e),
+        }
     };
 
-    if let Some(mut url) = doc_url {
+    Ok(doc_url.map(|mut url| {
         if let Some(item_path) = query_iter.next() {
             url += &format!("?search={}", item_path);
         }
+        url + note
+    }))
+}
+
+pub async fn doc_search(args: Arc<Args>) -> Result<(), Error> {
+    let query = args
+        .params
+        .get("query")
+        .ok_or("Unable to retrieve param: query")?;
 
+    if let Some(url) = doc_url(&args.http, query).await? {
         api::send_reply(args.clone(), &url).await?;
     } else {
         let message = "No crates found.";
@@ -124,12 +1708,140 @@ pub async fn doc_search(args: Arc<Args>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Link to the docs of a crate with the given features, checking that the crate has them.  
+///
+/// docs.rs builds one set of docs per version, so the link goes to the crate's feature list,
+/// which tells which features the docs were built with.  
+async fn feature_doc_url(
+    http: &HttpClient,
+    crate_name: &str,
+    features: &str,
+) -> Result<Option<String>, Error> {
+    let krate = match get_crate(http, crate_name).await? {
+        Some(krate) => krate,
+        None => return Ok(None),
+    };
+
+    let version = krate
+        .max_stable_version
+        .as_deref()
+        .unwrap_or(&krate.newest_version);
+    let versions = get_versions(http, &krate).await?;
+    let available = versions
+        .iter()
+        .find(|v| v.num == version)
+        .map(|v| &v.features);
+
+    Ok(Some(feature_doc_message(
+        &krate.name,
+        version,
+        available,
+        features,
+    )))
+}
+
+/// The link to the feature list of a crate's `version`, or which of the comma separated
+/// `features` it doesn't have when `available` lists its features.  
+fn feature_doc_message(
+    name: &str,
+    version: &str,
+    available: Option<&HashMap<String, Vec<String>>>,
+    features: &str,
+) -> String {
+    let requested = features
+        .split(',')
+        .map(str::trim)
+        .filter(|feature| !feature.is_empty())
+        .collect::<Vec<_>>();
+
+    // Without the version's feature list, link anyway rather than reject valid features
+    if let Some(available) = available {
+        let unknown = requested
+            .iter()
+            .filter(|feature| !available.contains_key(**feature))
+            .map(|feature| format!("`{}`", feature))
+            .collect::<Vec<_>>();
+
+        if !unknown.is_empty() {
+            let mut known = available.keys().map(|f| &f[..]).collect::<Vec<_>>();
+            known.sort_unstable();
+            let known = if known.is_empty() {
+                String::from("none")
+            } else {
+                known.join(", ")
+            };
+            return format!(
+                "{} {} has no feature {}. Available features: {}",
+                name,
+                version,
+                unknown.join(", "),
+                known
+            );
+        }
+    }
+
+    let gated = requested
+        .iter()
+        .map(|feature| format!("`{}`", feature))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "https://docs.rs/crate/{name}/{version}/features\nItems behind {gated} are marked as such in https://docs.rs/{name}/{version}",
+        name = name,
+        version = version,
+        gated = gated
+    )
+}
+
+/// Like `doc_search`, linking to the feature list of a crate when features are given.  
+pub async fn doc_features(args: Arc<Args>) -> Result<(), Error> {
+    let crate_name = args
+        .params
+        .get("crate")
+        .ok_or("Unable to retrieve param: crate")?;
+
+    let url = match args.params.get("features") {
+        Some(features) => feature_doc_url(&args.http, crate_name, features).await?,
+        None => doc_url(&args.http, crate_name).await?,
+    };
+
+    let message = url.unwrap_or_else(|| "No crates found.".to_string());
+    api::send_reply(args.clone(), &message).await?;
+    Ok(())
+}
+
+/// Handle the `/docs` slash command.  
+pub async fn slash_doc_search(
+    cx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    http: &HttpClient,
+) -> Result<(), Error> {
+    let query = slash::option(interaction, "query").ok_or("Unable to retrieve option: query")?;
+
+    let message = doc_url(http, query)
+        .await?
+        .unwrap_or_else(|| "No crates found.".to_string());
+    slash::respond(cx, interaction, &message).await?;
+
+    Ok(())
+}
+
 /// Print the help message
 pub async fn help(args: Arc<Args>) -> Result<(), Error> {
     let help_string = "search for a crate on crates.io
 ```
-?crate query...
-```";
+?crate query...                 Show the best match for a search.
+?crate {query} sort={}          Show the first match in another order: relevance, downloads,
+                                recent-downloads, newly-added or recently-updated.
+?crate keyword {keyword}        List the top crates for a keyword.
+?crate category {category}      List the top crates in a category.
+?crate deps {name}              List the direct dependencies of a crate.
+?crate changes {name} {from} {to}
+                                Compare two versions of a crate.
+?crate exact {name}             Show the crate with exactly this name.
+?crate text query...            Show the best match as plain text, without an embed.
+```
+Well-known standard library types like `Vec` or `HashMap` link to the std docs instead.";
     api::send_reply(args.clone(), &help_string).await?;
     Ok(())
 }
@@ -139,7 +1851,140 @@ pub async fn doc_help(args: Arc<Args>) -> Result<(), Error> {
     let help_string = "retrieve documentation for a given crate
 ```
 ?docs crate_name...
+?docs {crate} features={a,b}    Link to the crate's features, checking they exist.
 ```";
     api::send_reply(args.clone(), &help_string).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duration::{HOUR, MINUTE};
+    use std::time::SystemTime;
+
+    fn version(num: &str, yanked: bool) -> Version {
+        Version {
+            num: num.to_string(),
+            yanked,
+            license: None,
+            edition: None,
+            rust_version: None,
+            features: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn crate_text_is_cut_at_a_line() {
+        assert_eq!(truncate_lines("short\ntext", 20), "short\ntext");
+
+        let text = format!("{}\n{}\n{}", "a".repeat(10), "b".repeat(10), "c".repeat(10));
+        let cut = truncate_lines(&text, 25);
+        assert_eq!(cut, format!("{}\n{}\n…", "a".repeat(10), "b".repeat(10)));
+        assert!(cut.chars().count() <= 25);
+
+        let cut = truncate_lines(&"word ".repeat(10), 20);
+        assert!(cut.chars().count() <= 20);
+        assert!(cut.ends_with("word…"));
+    }
+
+    #[test]
+    fn advisories_link_to_their_database() {
+        assert_eq!(
+            advisory_url("RUSTSEC-2021-0001"),
+            "https://rustsec.org/advisories/RUSTSEC-2021-0001"
+        );
+        assert_eq!(
+            advisory_url("GHSA-2qv5-7mw5-j3cg"),
+            "https://osv.dev/vulnerability/GHSA-2qv5-7mw5-j3cg"
+        );
+    }
+
+    #[test]
+    fn close_names_are_shown_without_suggestions() {
+        assert!(suggestions("serde_json", &["serde-json", "serde"]).is_empty());
+        assert!(suggestions("tokio", &["tokoi", "futures"]).is_empty());
+        assert!(suggestions("anything", &[]).is_empty());
+    }
+
+    #[test]
+    fn distant_names_are_suggested_most_similar_first() {
+        // Equally similar names keep the order crates.io ranked them in
+        let names = ["json", "serde_json", "simd-json", "rand", "parsers"];
+        assert_eq!(
+            suggestions("jsn_parser", &names),
+            ["parsers", "json", "simd-json"]
+        );
+    }
+
+    #[test]
+    fn time_ago_is_pluralised() {
+        const DAY: u64 = 24 * HOUR;
+        let ago = |secs: u64| {
+            time_ago(DateTime::from(
+                SystemTime::now() - Duration::from_secs(secs),
+            ))
+        };
+
+        assert_eq!(ago(10), "just now");
+        assert_eq!(ago(90), "1 minute ago");
+        assert_eq!(ago(150 * MINUTE), "2 hours ago");
+        assert_eq!(ago(30 * HOUR), "1 day ago");
+        assert_eq!(ago(45 * DAY), "1 month ago");
+        assert_eq!(ago(364 * DAY), "12 months ago");
+        assert_eq!(ago(800 * DAY), "2 years ago");
+
+        let future = SystemTime::now() + Duration::from_secs(MINUTE);
+        assert_eq!(time_ago(DateTime::from(future)), "just now");
+    }
+
+    #[test]
+    fn latest_release_of_each_line() {
+        let versions = [
+            version("1.3.0", false),
+            version("2.0.0-alpha.1", false),
+            version("1.2.3", false),
+            version("0.12.4", true),
+            version("0.12.1", false),
+            version("0.0.3", false),
+        ];
+        assert_eq!(
+            latest_per_major(&versions),
+            [
+                (String::from("1.x"), String::from("1.3.0")),
+                (String::from("0.12.x"), String::from("0.12.1")),
+                (String::from("0.0.3"), String::from("0.0.3")),
+            ]
+        );
+
+        // Each pre-1.0 minor is its own line
+        let versions = [version("0.2.1", false), version("0.1.9", false)];
+        assert_eq!(latest_per_major(&versions).len(), 2);
+
+        let versions = [version("1.0.1", false), version("1.0.0", false)];
+        assert!(latest_per_major(&versions).is_empty());
+    }
+
+    #[test]
+    fn feature_links_check_the_features() {
+        let features = HashMap::from([
+            (String::from("derive"), Vec::new()),
+            (String::from("std"), Vec::new()),
+        ]);
+
+        assert_eq!(
+            feature_doc_message("serde", "1.0.0", Some(&features), "derive, std"),
+            "https://docs.rs/crate/serde/1.0.0/features\n\
+            Items behind `derive`, `std` are marked as such in https://docs.rs/serde/1.0.0"
+        );
+        assert_eq!(
+            feature_doc_message("serde", "1.0.0", Some(&features), "derive,rc"),
+            "serde 1.0.0 has no feature `rc`. Available features: derive, std"
+        );
+        assert_eq!(
+            feature_doc_message("serde", "1.0.0", Some(&HashMap::new()), "rc"),
+            "serde 1.0.0 has no feature `rc`. Available features: none"
+        );
+        assert!(feature_doc_message("serde", "1.0.0", None, "rc").starts_with("https://"));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::Error;
+use std::time::Duration;
+
+pub const MINUTE: u64 = 60;
+pub const HOUR: u64 = 60 * MINUTE;
+pub const DAY: u64 = 24 * HOUR;
+pub const WEEK: u64 = 7 * DAY;
+
+/// The longest duration accepted by `parse_duration`.  
+pub const MAX_DURATION: Duration = Duration::from_secs(365 * DAY);
+
+const UNITS: [(u64, &str); 5] = [
+    (WEEK, "week"),
+    (DAY, "day"),
+    (HOUR, "hour"),
+    (MINUTE, "minute"),
+    (1, "second"),
+];
+
+fn unit_seconds(unit: char) -> Option<u64> {
+    match unit {
+        's' => Some(1),
+        'm' => Some(MINUTE),
+        'h' => Some(HOUR),
+        'd' => Some(DAY),
+        'w' => Some(WEEK),
+        _ => None,
+    }
+}
+
+/// Parse a duration made of numbers followed by `s`, `m`, `h`, `d` or `w`, like `90s` or
+/// `1h30m`.  
+pub fn parse_duration(s: &str) -> Result<Duration, Error> {
+    let invalid = || format!("invalid duration `{}`, use something like `1h30m`", s);
+
+    let mut seconds: u64 = 0;
+    let mut number = String::new();
+
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+        } else {
+            let unit = unit_seconds(c).ok_or_else(invalid)?;
+            let count = number.parse::<u64>().map_err(|_| invalid())?;
+            seconds = count
+                .checked_mul(unit)
+                .and_then(|part| seconds.checked_add(part))
+                .ok_or_else(invalid)?;
+            number.clear();
+        }
+    }
+
+    if !number.is_empty() || s.is_empty() {
+        return Err(invalid().into());
+    }
+
+    let duration = Duration::from_secs(seconds);
+    if duration > MAX_DURATION {
+        return Err(format!(
+            "duration `{}` is too long, the maximum is {}",
+            s,
+            format_duration(MAX_DURATION)
+        )
+        .into());
+    }
+
+    Ok(duration)
+}
+
+/// Parse a duration like `parse_duration`, where a plain number is a count of `unit`.  
+pub fn parse_duration_in(s: &str, unit: Duration) -> Result<Duration, Error> {
+    match s.parse::<u32>() {
+        Ok(count) => parse_duration(&format!("{}s", unit.as_secs() * u64::from(count))),
+        Err(_) => parse_duration(s),
+    }
+}
+
+/// Format a duration like "1 day, 2 hours".  
+pub fn format_duration(duration: Duration) -> String {
+    let mut remaining = duration.as_secs();
+
+    let parts = UNITS
+        .iter()
+        .filter_map(|(seconds, name)| {
+            let count = remaining / seconds;
+            remaining %= seconds;
+            match count {
+                0 => None,
+                1 => Some(format!("1 {}", name)),
+                _ => Some(format!("{} {}s", count, name)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if parts.is_empty() {
+        String::from("0 seconds")
+    } else {
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: &str) -> u64 {
+        parse_duration(s).unwrap().as_secs()
+    }
+
+    #[test]
+    fn units() {
+        assert_eq!(secs("0s"), 0);
+        assert_eq!(secs("90s"), 90);
+        assert_eq!(secs("5m"), 5 * MINUTE);
+        assert_eq!(secs("2h"), 2 * HOUR);
+        assert_eq!(secs("3d"), 3 * DAY);
+        assert_eq!(secs("1w"), WEEK);
+    }
+
+    #[test]
+    fn combinations() {
+        assert_eq!(secs("1h30m"), HOUR + 30 * MINUTE);
+        assert_eq!(
+            secs("1w2d3h4m5s"),
+            WEEK + 2 * DAY + 3 * HOUR + 4 * MINUTE + 5
+        );
+        // Units can repeat and come in any order
+        assert_eq!(secs("30m1h"), HOUR + 30 * MINUTE);
+        assert_eq!(secs("1m1m"), 2 * MINUTE);
+    }
+
+    #[test]
+    fn invalid() {
+        for s in ["", "h", "10", "1h30", "1y", "-1h", "1 h", "1.5h"] {
+            assert!(parse_duration(s).is_err(), "`{}` should be invalid", s);
+        }
+    }
+
+    #[test]
+    fn overflow() {
+        assert!(parse_duration("99999999999999999999s").is_err());
+        assert!(parse_duration("18446744073709551615w").is_err());
+        assert!(parse_duration("18446744073709551615s1s").is_err());
+    }
+
+    #[test]
+    fn maximum() {
+        assert_eq!(parse_duration("365d").unwrap(), MAX_DURATION);
+        assert_eq!(parse_duration("52w1d").unwrap(), MAX_DURATION);
+
+        let too_long = parse_duration("365d1s").unwrap_err().to_string();
+        assert!(too_long.contains("too long"), "{}", too_long);
+        assert!(parse_duration("53w").is_err());
+    }
+
+    #[test]
+    fn plain_numbers_use_the_given_unit() {
+        let hour = Duration::from_secs(HOUR);
+        assert_eq!(parse_duration_in("2", hour).unwrap().as_secs(), 2 * HOUR);
+        assert_eq!(parse_duration_in("2m", hour).unwrap().as_secs(), 2 * MINUTE);
+        assert!(parse_duration_in("9000", hour).is_err());
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format_duration(Duration::from_secs(0)), "0 seconds");
+        assert_eq!(format_duration(Duration::from_secs(1)), "1 second");
+        assert_eq!(
+            format_duration(Duration::from_secs(DAY + 2 * HOUR)),
+            "1 day, 2 hours"
+        );
+        assert_eq!(
+            format_duration(Duration::from_secs(WEEK + MINUTE + 5)),
+            "1 week, 1 minute, 5 seconds"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use crate::{
+    api,
+    commands::{param_error, Args},
+    Error,
+};
+use serenity::utils::parse_username;
+use std::sync::Arc;
+use tracing::info;
+
+/// Delete everything stored about a user, for data deletion requests.  
+///
+/// Bans that haven't been lifted yet are deleted too, so they will no longer be lifted
+/// automatically.  
+pub async fn forget(args: Arc<Args>) -> Result<(), Error> {
+    let user = args
+        .params
+        .get("user")
+        .ok_or("Unable to retrieve param: user")?;
+
+    // Users who have left the server can only be given by id
+    let user_id = parse_username(user)
+        .or_else(|| user.parse::<u64>().ok())
+        .ok_or_else(|| param_error(format!("`{}` isn't a user mention or id", user)))?
+        .to_string();
+
+    info!("Deleting the stored data of user {}", &user_id);
+    let mut transaction = args.db.begin().await?;
+
+    let (active_bans,): (i64,) =
+        sqlx::query_as("select count(*) from bans where user_id = $1 and unbanned = false")
+            .bind(&user_id)
+            .fetch_one(&mut transaction)
+            .await?;
+
+    let bans = sqlx::query("delete from bans where user_id = $1")
+        .bind(&user_id)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    let tag_edits = sqlx::query("delete from tag_history where editor_id = $1")
+        .bind(&user_id)
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+
+    transaction.commit().await?;
+
+    let mut message = format!(
+        "Deleted {} ban(s) and {} tag edit(s) recorded for <@{}>.",
+        bans, tag_edits, user_id
+    );
+    if active_bans > 0 {
+        message += &format!(
+            "\n{} of the bans had not been lifted yet and will have to be lifted by hand.",
+            active_bans
+        );
+    }
+
+    api::send_reply(args.clone(), &message).await?;
+    Ok(())
+}
+
+/// Print the help message
+pub async fn help(args: Arc<Args>) -> Result<(), Error> {
+    let help_string = "
+Delete everything stored about a user, like their bans and tag edits
+```
+?forget {user}
+```
+**Example:**
+```
+?forget @someuser
+```
+The user can also be given by id, if they have left the server.
+Bans that are still in effect won't be lifted automatically anymore.";
+    api::send_reply(args.clone(), help_string).await?;
+    Ok(())
+}
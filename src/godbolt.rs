@@ -0,0 +1,180 @@
+use crate::{api, commands::Args, request_slots::request_slot, Error};
+use reqwest::{header, Client as HttpClient};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+
+const GODBOLT_COMPILER: &str = "nightly";
+const MAX_OUTPUT_LEN: usize = 1900;
+
+#[derive(Debug, Serialize)]
+struct GodboltRequest<'a> {
+    source: &'a str,
+    options: GodboltOptions,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GodboltOptions {
+    user_arguments: String,
+    filters: GodboltFilters,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GodboltFilters {
+    intel: bool,
+    directives: bool,
+    comment_only: bool,
+    labels: bool,
+    demangle: bool,
+    execute: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct GodboltLine {
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GodboltResponse {
+    code: i32,
+    #[serde(default)]
+    stderr: Vec<GodboltLine>,
+    #[serde(default)]
+    asm: Vec<GodboltLine>,
+    exec_result: Option<GodboltExecResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GodboltExecResult {
+    #[serde(default)]
+    stdout: Vec<GodboltLine>,
+    #[serde(default)]
+    stderr: Vec<GodboltLine>,
+}
+
+fn join_lines(lines: &[GodboltLine]) -> String {
+    lines
+        .iter()
+        .fold(String::new(), |output, line| output + &line.text + "\n")
+}
+
+/// Compile `code` on godbolt with the `compiler` id and rustc `user_arguments`, returning the
+/// generated assembly.  
+///
+/// When `execute` is true the compiled program is run instead and its output is returned.  
+async fn compile_rust_source(
+    http: &HttpClient,
+    code: &str,
+    execute: bool,
+    compiler: &str,
+    user_arguments: String,
+) -> Result<String, Error> {
+    let request = GodboltRequest {
+        source: code,
+        options: GodboltOptions {
+            user_arguments,
+            filters: GodboltFilters {
+                intel: true,
+                directives: true,
+                comment_only: true,
+                labels: true,
+                demangle: true,
+                execute,
+            },
+        },
+    };
+
+    let _slot = request_slot().await;
+    info!("compiling code on godbolt");
+    let response: GodboltResponse = http
+        .post(format!(
+            "https://godbolt.org/api/compiler/{}/compile",
+            compiler
+        ))
+        .header(header::ACCEPT, "application/json")
+        .json(&request)
+        .send()
+        .await?
+        .json()
+        .await?;
+
+    Ok(if response.code != 0 {
+        join_lines(&response.stderr)
+    } else if let Some(exec_result) = response.exec_result.filter(|_| execute) {
+        join_lines(&exec_result.stdout) + &join_lines(&exec_result.stderr)
+    } else {
+        join_lines(&response.asm)
+    })
+}
+
+/// Compile code on godbolt and reply with the assembly or the program's output.  
+pub async fn run(args: Arc<Args>) -> Result<(), Error> {
+    let code = args
+        .params
+        .get("code")
+        .ok_or("Unable to retrieve param: code")?;
+
+    let execute = args
+        .params
+        .get("execute")
+        .map(|s| &s[..])
+        .unwrap_or("false")
+        == "true";
+
+    send_output(
+        args.clone(),
+        code,
+        execute,
+        GODBOLT_COMPILER,
+        String::from("-O"),
+    )
+    .await
+}
+
+/// Compile code on godbolt and reply with the assembly, or the program's output when
+/// `execute` is true.  
+pub async fn send_output(
+    args: Arc<Args>,
+    code: &str,
+    execute: bool,
+    compiler: &str,
+    user_arguments: String,
+) -> Result<(), Error> {
+    let message = "*Compiling code on godbolt...*";
+    api::send_reply(args.clone(), message).await?;
+
+    let _typing = api::start_typing(&args);
+    let mut output =
+        compile_rust_source(&args.http, code, execute, compiler, user_arguments).await?;
+
+    output = api::escape_code_block(&output);
+
+    if output.trim().is_empty() {
+        output = String::from("no output");
+    } else if output.len() > MAX_OUTPUT_LEN {
+        let end = (0..=MAX_OUTPUT_LEN)
+            .rev()
+            .find(|i| output.is_char_boundary(*i))
+            .unwrap_or(0);
+        output.truncate(end);
+        output += "\n(output truncated)";
+    }
+
+    let lang = if execute { "" } else { "x86asm" };
+    api::send_reply(args.clone(), &format!("```{}\n{}```", lang, output)).await?;
+    Ok(())
+}
+
+pub async fn help(args: Arc<Args>) -> Result<(), Error> {
+    let message = "Compile rust code with https://godbolt.org and show the generated assembly.
+```?godbolt execute={} ``\u{200B}`code``\u{200B}` ```
+Optional arguments:
+    \texecute: boolean flag to run the program and show its output instead of the assembly
+Functions need to be `pub` to show up in the assembly.";
+
+    api::send_reply(args.clone(), message).await?;
+    Ok(())
+}
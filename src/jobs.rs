@@ -1,4 +1,10 @@
-use crate::{ban::unban_users, command_history::clear_command_history, Error, HOUR};
+use crate::{
+    ban::{prune_bans, unban_users},
+    command_history::clear_command_history,
+    crates::clear_advisory_cache,
+    presence::rotate_status,
+    Error, HOUR,
+};
 use serenity::client::Context;
 use sqlx::postgres::PgPool;
 use std::sync::{
@@ -6,16 +12,30 @@ use std::sync::{
     Arc,
 };
 use tokio::time::{sleep, Duration};
+use tracing::error;
 
 static JOBS_THREAD_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
 pub fn start_jobs(cx: Context, db: Arc<PgPool>) {
-    if !JOBS_THREAD_INITIALIZED.load(Ordering::SeqCst) {
-        JOBS_THREAD_INITIALIZED.store(true, Ordering::SeqCst);
+    // `ready` fires again on every reconnect, only the first call may start the loop
+    if JOBS_THREAD_INITIALIZED
+        .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+    {
         tokio::spawn(async move {
             loop {
                 unban_users(&cx, db.clone()).await?;
+                // A bad `ban_retention_days` setting shouldn't stop bans from being lifted
+                if let Err(e) = prune_bans(db.clone()).await {
+                    error!("Unable to prune old bans: {}", e);
+                }
                 clear_command_history(&cx).await?;
+                if let Err(e) = clear_advisory_cache(&cx).await {
+                    error!("Unable to clear the advisory cache: {}", e);
+                }
+                if let Err(e) = rotate_status(&cx).await {
+                    error!("Unable to rotate the status: {}", e);
+                }
 
                 sleep(Duration::new(HOUR, 0)).await;
             }
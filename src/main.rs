@@ -6,13 +6,26 @@ extern crate diesel_migrations;
 
 mod api;
 mod ban;
+mod broadcast;
 mod command_history;
 mod commands;
+mod confirmation;
 mod crates;
 mod db;
+mod duration;
+mod forget;
+mod godbolt;
 mod jobs;
+mod menu;
+mod metrics;
 mod playground;
+mod presence;
+mod request_slots;
+mod roles;
 mod schema;
+mod settings;
+mod slash;
+mod sql;
 mod state_machine;
 mod tags;
 mod text;
@@ -20,15 +33,19 @@ mod welcome;
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 
-pub const HOUR: u64 = 3600;
+pub use duration::HOUR;
 
-use crate::commands::{Command, Commands};
+use crate::commands::{Command, Commands, MenuFilter};
 use indexmap::IndexMap;
 use reqwest::Client as HttpClient;
 use serde::Deserialize;
-use serenity::{async_trait, model::prelude::*, prelude::*};
+use serenity::{
+    async_trait,
+    model::{application::interaction::Interaction, prelude::*},
+    prelude::*,
+};
 use sqlx::postgres::{PgPool, PgPoolOptions};
-use std::sync::Arc;
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
 use tracing::{error, info};
 
 #[derive(Deserialize)]
@@ -40,6 +57,31 @@ struct Config {
     mod_id: String,
     talk_id: String,
     wg_and_teams_id: Option<String>,
+    #[serde(default)]
+    confirm_destructive: bool,
+    #[serde(default)]
+    slash_commands: bool,
+    #[serde(default = "default_lib_rs_links")]
+    lib_rs_links: bool,
+    #[serde(default = "default_status")]
+    status: String,
+    metrics_port: Option<u16>,
+    #[serde(default = "default_metrics_address")]
+    metrics_address: IpAddr,
+    #[serde(default)]
+    play_threads: bool,
+}
+
+fn default_lib_rs_links() -> bool {
+    true
+}
+
+fn default_metrics_address() -> IpAddr {
+    IpAddr::from([127, 0, 0, 1])
+}
+
+fn default_status() -> String {
+    String::from("for ?help")
 }
 
 async fn upsert_role(
@@ -49,7 +91,7 @@ async fn upsert_role(
 ) -> Result<(), Error> {
     sqlx::query(
         "insert into roles(role, name) values ($1, $2)
-                on conflict (name) do update set role = $1",
+                on conflict (name, guild_id) do update set role = $1",
     )
     .bind(role_id)
     .bind(name)
@@ -59,19 +101,28 @@ async fn upsert_role(
     Ok(())
 }
 
-async fn init_data(config: &Config, pool: Arc<PgPool>) -> Result<(), Error> {
-    info!("Loading data into database");
+/// Write the global mod, talk and wg_and_teams roles to the database.  
+///
+/// The `mod_id`, `talk_id` and `wg_and_teams_id` settings take precedence over the
+/// environment so roles can be changed without a restart.  
+async fn seed_roles(config: &Config, pool: &PgPool) -> Result<(), Error> {
+    let mod_id = settings::get(pool, "mod_id")
+        .await?
+        .unwrap_or_else(|| config.mod_id.clone());
+    let talk_id = settings::get(pool, "talk_id")
+        .await?
+        .unwrap_or_else(|| config.talk_id.clone());
+    let wg_and_teams_id = settings::get(pool, "wg_and_teams_id")
+        .await?
+        .or_else(|| config.wg_and_teams_id.clone());
 
     let mut transaction = pool.begin().await?;
 
-    upsert_role("mod", &config.mod_id, &mut transaction).await?;
-    upsert_role("talk", &config.talk_id, &mut transaction).await?;
+    upsert_role("mod", &mod_id, &mut transaction).await?;
+    upsert_role("talk", &talk_id, &mut transaction).await?;
 
     if config.tags || config.crates {
-        let wg_and_teams_role = config
-            .wg_and_teams_id
-            .as_ref()
-            .ok_or(text::WG_AND_TEAMS_MISSING_ENV_VAR)?;
+        let wg_and_teams_role = wg_and_teams_id.ok_or(text::WG_AND_TEAMS_MISSING_ENV_VAR)?;
         upsert_role("wg_and_teams", &wg_and_teams_role, &mut transaction).await?;
     }
 
@@ -80,6 +131,22 @@ async fn init_data(config: &Config, pool: Arc<PgPool>) -> Result<(), Error> {
     Ok(())
 }
 
+async fn init_data(config: &Config, pool: Arc<PgPool>) -> Result<(), Error> {
+    info!("Loading data into database");
+
+    seed_roles(config, &pool).await
+}
+
+/// Re-seed the roles from the current environment and settings.  
+async fn reload_config(args: Arc<commands::Args>) -> Result<(), Error> {
+    info!("Reloading roles");
+    let config = envy::from_env::<Config>()?;
+    seed_roles(&config, &args.db).await?;
+
+    args.msg.react(&args.cx, '✅').await?;
+    Ok(())
+}
+
 async fn app() -> Result<(), Error> {
     let config = envy::from_env::<Config>()?;
 
@@ -97,13 +164,23 @@ async fn app() -> Result<(), Error> {
 
     let _ = init_data(&config, pool.clone()).await?;
 
+    if let Some(port) = config.metrics_port {
+        let address = config.metrics_address;
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(address, port).await {
+                error!("{}", e);
+            }
+        });
+    }
+
     let mut cmds = Commands::new();
 
     if config.tags {
         // Tags
         cmds.add(
             "?tags delete {key}",
-            Command::new_with_auth(&tags::delete, &api::is_wg_and_teams),
+            Command::new_with_auth(&tags::delete, &api::is_wg_and_teams)
+                .with_confirmation(config.confirm_destructive),
         );
         cmds.add(
             "?tags create {key} value...",
@@ -113,6 +190,17 @@ async fn app() -> Result<(), Error> {
             "?tags update {key} value...",
             Command::new_with_auth(&tags::update, &api::is_wg_and_teams),
         );
+        cmds.add(
+            "?tags lock {key}",
+            Command::new_with_auth(&tags::lock, &api::is_admin),
+        );
+        cmds.add(
+            "?tags unlock {key}",
+            Command::new_with_auth(&tags::unlock, &api::is_admin),
+        );
+        cmds.add("?tags history {key}", Command::new(&tags::history));
+        cmds.add("?tags random", Command::new(&tags::random));
+        cmds.add("?tags count", Command::new(&tags::count));
         cmds.add("?tag {key}", Command::new(&tags::get));
         cmds.add("?tags", Command::new(&tags::get_all));
         cmds.help("?tags", "A key value store", Command::new(&tags::help));
@@ -120,6 +208,25 @@ async fn app() -> Result<(), Error> {
 
     if config.crates {
         // crates.io
+        cmds.add(
+            "?crate keyword {keyword}",
+            Command::new(&crates::keyword_search),
+        );
+        cmds.add(
+            "?crate category {category}",
+            Command::new(&crates::category_search),
+        );
+        cmds.add("?crate deps {name}", Command::new(&crates::dependencies));
+        cmds.add(
+            "?crate changes {name} {from} {to}",
+            Command::new(&crates::changes),
+        );
+        cmds.add("?crate exact {name}", Command::new(&crates::exact_search));
+        cmds.add("?crate text query...", Command::new(&crates::text_search));
+        cmds.add(
+            "?crate {query} sort={}",
+            Command::new(&crates::sorted_search),
+        );
         cmds.add("?crate query...", Command::new(&crates::search));
         cmds.help(
             "?crate",
@@ -128,6 +235,10 @@ async fn app() -> Result<(), Error> {
         );
 
         // docs.rs
+        cmds.add(
+            "?docs {crate} features={}",
+            Command::new(&crates::doc_features),
+        );
         cmds.add("?docs query...", Command::new(&crates::doc_search));
         cmds.help(
             "?docs",
@@ -139,10 +250,14 @@ async fn app() -> Result<(), Error> {
     if config.eval {
         // rust playground
         cmds.add(
-            "?play mode={} edition={} channel={} warn={} ```\ncode``` ...",
+            "?play mode={} edition={} channel={} crate={} warn={} gist={} verbose={} output={} ```\ncode``` ...",
             Command::new(&playground::run),
         );
-        cmds.add("?play code...", Command::new(&playground::err));
+        cmds.add(
+            "?play mode={} edition={} channel={} crate={} warn={} gist={} verbose={} output={}",
+            Command::new(&playground::run_reply),
+        );
+        cmds.add("?play code...", Command::new(&playground::run_with_flags));
         cmds.help(
             "?play",
             "Compile and run rust code in a playground",
@@ -150,60 +265,236 @@ async fn app() -> Result<(), Error> {
         );
 
         cmds.add(
-            "?eval mode={} edition={} channel={} warn={} ```\ncode``` ...",
+            "?eval mode={} edition={} channel={} warn={} gist={} verbose={} fmt={} ```\ncode``` ...",
             Command::new(&playground::eval),
         );
         cmds.add(
-            "?eval mode={} edition={} channel={} warn={} ```code``` ...",
+            "?eval mode={} edition={} channel={} warn={} gist={} verbose={} fmt={} ```code``` ...",
             Command::new(&playground::eval),
         );
         cmds.add(
-            "?eval mode={} edition={} channel={} warn={} `code` ...",
+            "?eval mode={} edition={} channel={} warn={} gist={} verbose={} fmt={} `code` ...",
             Command::new(&playground::eval),
         );
+        cmds.add(
+            "?eval mode={} edition={} channel={} warn={} gist={} verbose={} fmt={}",
+            Command::new(&playground::eval_reply),
+        );
         cmds.add("?eval code...", Command::new(&playground::eval_err));
         cmds.help(
             "?eval",
             "Evaluate a single rust expression",
             Command::new(&|args| async { playground::help(args, "eval").await }),
         );
+
+        // godbolt
+        cmds.add(
+            "?godbolt execute={} ```\ncode``` ...",
+            Command::new(&godbolt::run),
+        );
+        cmds.add("?godbolt code...", Command::new(&playground::err));
+        cmds.add("?godbolt", Command::new(&playground::err));
+        cmds.help(
+            "?godbolt",
+            "Show the assembly generated for rust code",
+            Command::new(&godbolt::help),
+        );
     }
 
+    // Settings
+    cmds.add(
+        "?config get {key}",
+        Command::new_with_auth(&settings::get_command, &api::is_admin),
+    );
+    cmds.add(
+        "?config set {key} value...",
+        Command::new_with_auth(&settings::set_command, &api::is_admin),
+    );
+    cmds.add(
+        "?config",
+        Command::new_with_auth(&settings::get_all, &api::is_admin),
+    );
+    cmds.add(
+        "?config role {name} {role}",
+        Command::new_with_auth(&settings::set_role, &api::is_admin),
+    );
+    cmds.add(
+        "?reloadconfig",
+        Command::new_with_auth(&reload_config, &api::is_admin),
+    );
+    cmds.add(
+        "?disable {command}",
+        Command::new_with_auth(&settings::disable, &api::is_admin),
+    );
+    cmds.add(
+        "?enable {command}",
+        Command::new_with_auth(&settings::enable, &api::is_admin),
+    );
+    cmds.add(
+        "?alias remove {short}",
+        Command::new_with_auth(&settings::remove_alias, &api::is_admin),
+    );
+    cmds.add(
+        "?alias {short} {full}",
+        Command::new_with_auth(&settings::alias, &api::is_admin),
+    );
+    cmds.help(
+        "?config",
+        "Show and change live settings",
+        Command::new_with_auth(&settings::help, &api::is_admin),
+    );
+
     // Slow mode.
-    // 0 seconds disables slowmode
+    // A duration of 0 disables slowmode
+    cmds.add(
+        "?slowmode list",
+        Command::new_with_auth(&api::slow_mode_list, &api::is_mod),
+    );
     cmds.add(
-        "?slowmode {channel} {seconds}",
+        "?slowmode clear",
+        Command::new_with_auth(&api::slow_mode_clear, &api::is_mod)
+            .with_confirmation(config.confirm_destructive),
+    );
+    cmds.add(
+        "?slowmode {channel} {duration}",
         Command::new_with_auth(&api::slow_mode, &api::is_mod),
     );
     cmds.help(
         "?slowmode",
         "Set slowmode on a channel",
-        Command::new_with_auth(&api::slow_mode_help, &api::is_mod),
+        Command::new_with_auth(&api::slow_mode_help, &api::is_mod)
+            .with_example("?slowmode #general 30s"),
+    );
+
+    // Lock and unlock channels
+    cmds.add(
+        "?lock {channel}",
+        Command::new_with_auth(&api::lock, &api::is_mod),
+    );
+    cmds.add(
+        "?unlock {channel}",
+        Command::new_with_auth(&api::unlock, &api::is_mod),
+    );
+    cmds.help(
+        "?lock",
+        "Stop everyone from posting in a channel",
+        Command::new_with_auth(&api::lock_help, &api::is_mod).with_example("?lock #general"),
     );
 
     // Kick
     cmds.add(
         "?kick {user}",
-        Command::new_with_auth(&api::kick, &api::is_mod),
+        Command::new_with_auth(&api::kick, &api::is_mod)
+            .with_confirmation(config.confirm_destructive),
     );
     cmds.help(
         "?kick",
         "Kick a user from the guild",
-        Command::new_with_auth(&api::kick_help, &api::is_mod),
+        Command::new_with_auth(&api::kick_help, &api::is_mod).with_example("?kick @someone"),
+    );
+
+    // Purge
+    cmds.add(
+        "?purge {count}",
+        Command::new_with_auth(&api::purge, &api::is_mod)
+            .with_confirmation(config.confirm_destructive),
+    );
+    cmds.help(
+        "?purge",
+        "Delete the most recent messages in a channel",
+        Command::new_with_auth(&api::purge_help, &api::is_mod).with_example("?purge 10"),
     );
 
     // Ban
     cmds.add(
-        "?ban {user} {hours} reason...",
-        Command::new_with_auth(&ban::temp_ban, &api::is_mod),
+        "?ban {user} {duration} reason...",
+        Command::new_with_auth(&ban::temp_ban, &api::is_mod)
+            .with_confirmation(config.confirm_destructive),
     );
     cmds.help(
         "?ban",
         "Temporarily ban a user from the guild",
-        Command::new_with_auth(&ban::help, &api::is_mod),
+        Command::new_with_auth(&ban::help, &api::is_mod)
+            .with_example("?ban @someone 1d12h posting spam"),
+    );
+
+    // Data deletion
+    cmds.add(
+        "?forget {user}",
+        Command::new_with_auth(&forget::forget, &api::is_admin)
+            .with_confirmation(config.confirm_destructive),
+    );
+    cmds.help(
+        "?forget",
+        "Delete everything stored about a user",
+        Command::new_with_auth(&forget::help, &api::is_admin).with_example("?forget @someone"),
+    );
+
+    // Database inspection
+    cmds.add(
+        "?sql query...",
+        Command::new_with_auth(&sql::sql, &api::is_admin),
+    );
+    cmds.help(
+        "?sql",
+        "Run a read-only query against the database",
+        Command::new_with_auth(&sql::help, &api::is_admin),
+    );
+
+    // Self-assignable roles
+    cmds.add(
+        "?roles add {name} {role}",
+        Command::new_with_auth(&roles::add, &api::is_mod),
+    );
+    cmds.add(
+        "?roles remove {name}",
+        Command::new_with_auth(&roles::remove, &api::is_mod),
+    );
+    cmds.add("?roles", Command::new(&roles::list));
+    cmds.add("?iam {role}", Command::new(&roles::iam));
+    cmds.add("?iamnot {role}", Command::new(&roles::iamnot));
+    cmds.help(
+        "?roles",
+        "Give yourself roles, like the one for announcements",
+        Command::new(&roles::help).with_example("?roles add announcements @Announcements"),
+    );
+
+    cmds.add("?ping", Command::new(&api::ping));
+    cmds.help(
+        "?ping",
+        "Check the bot's connection to discord and its database",
+        Command::new(&api::ping_help),
+    );
+
+    // Direct message test
+    cmds.add("?dmtest", Command::new(&api::dm_test));
+    cmds.help(
+        "?dmtest",
+        "Check that the bot can send you direct messages",
+        Command::new(&api::dm_test_help),
+    );
+
+    // Announcements
+    cmds.add(
+        "?broadcast message...",
+        Command::new_with_auth(&broadcast::broadcast, &api::is_admin)
+            .with_confirmation(config.confirm_destructive),
+    );
+    cmds.help(
+        "?broadcast",
+        "Post a message to several channels",
+        Command::new_with_auth(&broadcast::help, &api::is_admin),
     );
 
     // Post the welcome message to the welcome channel.
+    cmds.add(
+        "?CoC set text...",
+        Command::new_with_auth(&welcome::set_text, &api::is_mod),
+    );
+    cmds.add(
+        "?CoC emoji {emoji}",
+        Command::new_with_auth(&welcome::set_emoji, &api::is_mod),
+    );
     cmds.add(
         "?CoC {channel}",
         Command::new_with_auth(&welcome::post_message, &api::is_mod),
@@ -211,9 +502,11 @@ async fn app() -> Result<(), Error> {
     cmds.help(
         "?CoC",
         "Post the code of conduct message to a channel",
-        Command::new_with_auth(&welcome::help, &api::is_mod),
+        Command::new_with_auth(&welcome::help, &api::is_mod).with_example("?CoC #welcome"),
     );
 
+    cmds.add("?help mod", Command::filtered_help(MenuFilter::Protected));
+    cmds.add("?help public", Command::filtered_help(MenuFilter::Public));
     cmds.add("?help", Command::help());
 
     let mut client = Client::builder(&config.discord_token, GatewayIntents::all())
@@ -221,9 +514,27 @@ async fn app() -> Result<(), Error> {
             http: Arc::new(HttpClient::new()),
             db: pool.clone(),
             cmds: Arc::new(cmds),
+            slash_commands: config.slash_commands,
+            statuses: presence::parse_statuses(&config.status),
         })
         .await?;
 
+    client
+        .data
+        .write()
+        .await
+        .insert::<api::ShardManagerContainer>(client.shard_manager.clone());
+    client
+        .data
+        .write()
+        .await
+        .insert::<playground::OutputThreads>(config.play_threads);
+    client
+        .data
+        .write()
+        .await
+        .insert::<crates::LibRsLinks>(config.lib_rs_links);
+
     client.start().await?;
 
     Ok(())
@@ -241,6 +552,8 @@ struct Events {
     http: Arc<HttpClient>,
     db: Arc<PgPool>,
     cmds: Arc<Commands>,
+    slash_commands: bool,
+    statuses: Vec<String>,
 }
 
 #[async_trait]
@@ -248,8 +561,34 @@ impl EventHandler for Events {
     async fn ready(&self, cx: Context, ready: Ready) {
         info!("{} connected to discord", ready.user.name);
         {
+            // `ready` fires again after a reconnect, which must not lose the state built up so far
             let mut data = cx.data.write().await;
-            data.insert::<command_history::CommandHistory>(IndexMap::new());
+            data.entry::<command_history::CommandHistory>()
+                .or_insert_with(IndexMap::new);
+            data.entry::<confirmation::PendingConfirmations>()
+                .or_insert_with(HashMap::new);
+            data.entry::<menu::HelpMenus>()
+                .or_insert_with(IndexMap::new);
+            data.entry::<crates::AdvisoryCache>()
+                .or_insert_with(HashMap::new);
+            data.entry::<presence::Statuses>()
+                .or_insert_with(|| (self.statuses.clone(), 0));
+        }
+
+        if self.slash_commands {
+            if let Err(e) = slash::register(&cx).await {
+                error!("{}", e);
+            }
+        }
+
+        {
+            let cx = cx.clone();
+            let db = self.db.clone();
+            tokio::spawn(async move {
+                if let Err(e) = welcome::resume_reactions(&cx, &db).await {
+                    error!("{}", e);
+                }
+            });
         }
 
         jobs::start_jobs(cx, self.db.clone());
@@ -264,13 +603,19 @@ impl EventHandler for Events {
     async fn message_update(
         &self,
         cx: Context,
-        _: Option<Message>,
+        old: Option<Message>,
         _: Option<Message>,
         ev: MessageUpdateEvent,
     ) {
-        if let Err(e) =
-            command_history::replay_message(cx, ev, &self.cmds, self.http.clone(), self.db.clone())
-                .await
+        if let Err(e) = command_history::replay_message(
+            cx,
+            old,
+            ev,
+            &self.cmds,
+            self.http.clone(),
+            self.db.clone(),
+        )
+        .await
         {
             error!("{}", e);
         }
@@ -292,11 +637,30 @@ impl EventHandler for Events {
     }
 
     async fn reaction_add(&self, cx: Context, reaction: Reaction) {
+        if let Err(e) = confirmation::handle_reaction(&cx, &reaction).await {
+            error!("{}", e);
+        }
         if let Err(e) = welcome::assign_talk_role(&cx, &reaction, self.db.clone()).await {
             error!("{}", e);
         }
     }
 
+    async fn interaction_create(&self, cx: Context, interaction: Interaction) {
+        let result = match interaction {
+            Interaction::MessageComponent(component) => {
+                menu::handle_interaction(&cx, &component).await
+            }
+            Interaction::ApplicationCommand(command) => {
+                slash::execute(&cx, &command, &self.http).await
+            }
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            error!("{}", e);
+        }
+    }
+
     async fn guild_ban_removal(&self, _cx: Context, guild_id: GuildId, user: User) {
         if let Err(e) = ban::save_unban(
             format!("{}", user.id),
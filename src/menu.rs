@@ -0,0 +1,110 @@
+use crate::{api, commands::Args, Error};
+use indexmap::IndexMap;
+use serenity::{
+    builder::CreateComponents,
+    model::{
+        application::{
+            component::ButtonStyle,
+            interaction::{
+                message_component::MessageComponentInteraction, InteractionResponseType,
+            },
+        },
+        prelude::*,
+    },
+    prelude::*,
+};
+use std::sync::Arc;
+
+const BUTTON_PREFIX: &str = "help_page:";
+const MAX_MENUS: usize = 100;
+
+pub struct HelpMenus;
+
+impl TypeMapKey for HelpMenus {
+    type Value = IndexMap<MessageId, Vec<String>>;
+}
+
+fn buttons(page: usize, pages: usize) -> CreateComponents {
+    let mut components = CreateComponents::default();
+    components.create_action_row(|row| {
+        row.create_button(|b| {
+            b.custom_id(format!("{}{}", BUTTON_PREFIX, page.saturating_sub(1)))
+                .label("Prev")
+                .style(ButtonStyle::Secondary)
+                .disabled(page == 0)
+        })
+        .create_button(|b| {
+            b.custom_id(format!("{}{}", BUTTON_PREFIX, page + 1))
+                .label("Next")
+                .style(ButtonStyle::Secondary)
+                .disabled(page + 1 >= pages)
+        })
+    });
+    components
+}
+
+fn render(pages: &[String], page: usize) -> String {
+    format!("```{}\n\nPage {}/{}```", pages[page], page + 1, pages.len())
+}
+
+/// Send the help menu, adding Prev/Next buttons when it spans more than one page.  
+pub async fn send_menu(args: Arc<Args>, pages: Vec<String>) -> Result<(), Error> {
+    if pages.len() < 2 {
+        let menu = pages.first().map(String::as_str).unwrap_or_default();
+        return api::send_reply(args.clone(), &format!("```{}```", menu)).await;
+    }
+
+    let response_id =
+        api::send_or_edit_components(args.clone(), &render(&pages, 0), buttons(0, pages.len()))
+            .await?;
+
+    let mut data = args.cx.data.write().await;
+    let menus = data.get_mut::<HelpMenus>().unwrap();
+    menus.insert(response_id, pages);
+    if menus.len() > MAX_MENUS {
+        menus.shift_remove_index(0);
+    }
+
+    Ok(())
+}
+
+/// Flip the page of a help menu when one of its buttons is clicked.  
+pub async fn handle_interaction(
+    cx: &Context,
+    interaction: &MessageComponentInteraction,
+) -> Result<(), Error> {
+    let page = match interaction.data.custom_id.strip_prefix(BUTTON_PREFIX) {
+        Some(page) => page.parse::<usize>()?,
+        None => return Ok(()),
+    };
+
+    let content = {
+        let data = cx.data.read().await;
+        let menus = data.get::<HelpMenus>().unwrap();
+        menus
+            .get(&interaction.message.id)
+            .filter(|pages| page < pages.len())
+            .map(|pages| (render(pages, page), buttons(page, pages.len())))
+    };
+
+    if let Some((content, components)) = content {
+        interaction
+            .create_interaction_response(cx, |r| {
+                r.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| d.content(content).set_components(components))
+            })
+            .await?;
+    } else {
+        interaction
+            .create_interaction_response(cx, |r| {
+                r.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|d| {
+                        d.content("This help menu has expired, use ?help to open a new one.")
+                            .ephemeral(true)
+                    })
+            })
+            .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,151 @@
+use crate::Error;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{error, info};
+
+/// Upper bounds, in seconds, of the playground latency histogram buckets.  
+const PLAYGROUND_BUCKETS: [f64; 8] = [0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 20.0, 30.0];
+
+struct Histogram {
+    buckets: [u64; PLAYGROUND_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+static COMMANDS: Mutex<BTreeMap<&'static str, u64>> = Mutex::new(BTreeMap::new());
+static ERRORS: AtomicU64 = AtomicU64::new(0);
+static PLAYGROUND_LATENCY: Mutex<Histogram> = Mutex::new(Histogram {
+    buckets: [0; PLAYGROUND_BUCKETS.len()],
+    sum: 0.0,
+    count: 0,
+});
+
+/// Count an execution of the command registered with `pattern`.  
+pub fn record_command(pattern: &'static str) {
+    *COMMANDS.lock().unwrap().entry(pattern).or_insert(0) += 1;
+}
+
+/// Count a command that returned an error.  
+pub fn record_error() {
+    ERRORS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record how long the playground took to respond.  
+pub fn record_playground_latency(latency: Duration) {
+    let seconds = latency.as_secs_f64();
+    let mut histogram = PLAYGROUND_LATENCY.lock().unwrap();
+
+    PLAYGROUND_BUCKETS
+        .iter()
+        .zip(histogram.buckets.iter_mut())
+        .filter(|(bound, _)| seconds <= **bound)
+        .for_each(|(_, bucket)| *bucket += 1);
+    histogram.sum += seconds;
+    histogram.count += 1;
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render the metrics in the prometheus text format.  
+fn render() -> String {
+    let mut output = String::new();
+
+    output += "# HELP discord_bot_commands_total Commands executed, by command.\n";
+    output += "# TYPE discord_bot_commands_total counter\n";
+    for (pattern, count) in COMMANDS.lock().unwrap().iter() {
+        let _ = writeln!(
+            output,
+            "discord_bot_commands_total{{command=\"{}\"}} {}",
+            escape_label(pattern),
+            count
+        );
+    }
+
+    output += "# HELP discord_bot_command_errors_total Commands that returned an error.\n";
+    output += "# TYPE discord_bot_command_errors_total counter\n";
+    let _ = writeln!(
+        output,
+        "discord_bot_command_errors_total {}",
+        ERRORS.load(Ordering::Relaxed)
+    );
+
+    output += "# HELP discord_bot_playground_seconds Time taken by the playground to run code.\n";
+    output += "# TYPE discord_bot_playground_seconds histogram\n";
+    let histogram = PLAYGROUND_LATENCY.lock().unwrap();
+    for (bound, count) in PLAYGROUND_BUCKETS.iter().zip(histogram.buckets.iter()) {
+        let _ = writeln!(
+            output,
+            "discord_bot_playground_seconds_bucket{{le=\"{}\"}} {}",
+            bound, count
+        );
+    }
+    let _ = writeln!(
+        output,
+        "discord_bot_playground_seconds_bucket{{le=\"+Inf\"}} {}",
+        histogram.count
+    );
+    let _ = writeln!(
+        output,
+        "discord_bot_playground_seconds_sum {}",
+        histogram.sum
+    );
+    let _ = writeln!(
+        output,
+        "discord_bot_playground_seconds_count {}",
+        histogram.count
+    );
+
+    output
+}
+
+async fn respond(mut stream: TcpStream) -> Result<(), Error> {
+    let mut request = [0; 1024];
+    let len = stream.read(&mut request).await?;
+
+    let response = if request[..len].starts_with(b"GET /metrics ") {
+        let body = render();
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        String::from("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+    };
+
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Serve the metrics at `/metrics` on `address` and `port`.  
+pub async fn serve(address: IpAddr, port: u16) -> Result<(), Error> {
+    let listener = TcpListener::bind((address, port)).await?;
+    info!("Serving metrics on {}:{}", address, port);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(async move {
+            if let Err(e) = respond(stream).await {
+                error!("{}", e);
+            }
+        });
+    }
+}
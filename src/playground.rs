@@ -1,14 +1,47 @@
 //! run rust code on the rust-lang playground
 
-use crate::{api, commands::Args, Error};
-use reqwest::header;
+use crate::{
+    api,
+    commands::{Args, PREFIX},
+    godbolt, metrics,
+    request_slots::{self, request_slot},
+    slash, Error,
+};
+use reqwest::{header, Client as HttpClient};
 use serde::{Deserialize, Serialize};
+use serenity::{
+    model::{
+        application::interaction::application_command::ApplicationCommandInteraction,
+        channel::ChannelType,
+    },
+    prelude::*,
+};
 use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tracing::info;
 
 const MAX_OUTPUT_LINES: usize = 45;
+/// Room left in a 2000 character message for the output once it is put in a code block.  
+const MAX_OUTPUT_LEN: usize = 1993;
+/// How long to wait for the playground to run code before giving up on it.  
+const PLAYGROUND_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_ERROR_CODES: usize = 5;
+/// Runs of at least this many identical lines in the output are shown once with a count.  
+const MIN_REPEATED_LINES: usize = 3;
+const TIMEOUT_MESSAGE: &str = "Your code timed out (possible infinite loop).";
+
+/// Outputs longer than this are posted in a thread, when `OutputThreads` is enabled.  
+const THREAD_OUTPUT_LEN: usize = 500;
+const THREAD_OUTPUT_LINES: usize = 15;
+
+/// Whether long outputs are posted in a thread off the command, from the `play_threads` config.  
+pub struct OutputThreads;
+
+impl TypeMapKey for OutputThreads {
+    type Value = bool;
+}
 
 #[derive(Debug, Serialize)]
 struct PlaygroundCode {
@@ -110,6 +143,18 @@ enum CrateType {
     Library,
 }
 
+impl FromStr for CrateType {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "bin" => Ok(CrateType::Binary),
+            "lib" => Ok(CrateType::Library),
+            _ => Err(format!("invalid crate type `{}`", s).into()),
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "snake_case")]
 enum Mode {
@@ -134,19 +179,157 @@ struct PlayResult {
     success: bool,
     stdout: String,
     stderr: String,
+    /// How the process ended, like `Exited with status 101`, left out by older playgrounds.  
+    #[serde(default, rename = "exitDetail")]
+    exit_detail: String,
+}
+
+impl PlayResult {
+    /// Tell a program that failed at runtime apart from one that failed to compile, and say how
+    /// it ended.  
+    ///
+    /// Cargo only prints `Running` once the build succeeded, so failures without it are compiler
+    /// errors, which explain themselves.  
+    fn exit_note(&self) -> Option<String> {
+        if self.success || !self.stderr.contains("Running `") {
+            return None;
+        }
+
+        let status = match self.exit_detail.trim() {
+            "" => String::new(),
+            detail => format!(" ({})", detail.to_lowercase()),
+        };
+        let note = if self.stderr.contains("panicked at") {
+            "The program panicked"
+        } else {
+            "The program exited with a non-zero status"
+        };
+
+        Some(format!("*{}{}.*", note, status))
+    }
+}
+
+/// Run `code` on the playground, sharing `source` instead when the output links to a gist.  
+async fn run_code(args: Arc<Args>, code: String, source: &str) -> Result<String, Error> {
+    let message = if request_slots::all_taken() {
+        "*Queued, running shortly...*"
+    } else {
+        "*Running code on playground...*"
+    };
+    api::send_reply(args.clone(), message).await?;
+
+    let _typing = api::start_typing(&args);
+    execute_code(&args.http, &args.params, code, source).await
+}
+
+/// Reply with the output of `?play` or `?eval`, moving long outputs into a thread when
+/// `OutputThreads` is enabled.  
+async fn send_output(args: Arc<Args>, output: &str) -> Result<(), Error> {
+    let threads = args
+        .cx
+        .data
+        .read()
+        .await
+        .get::<OutputThreads>()
+        .copied()
+        .unwrap_or(false);
+    let long = output.len() > THREAD_OUTPUT_LEN || output.lines().count() > THREAD_OUTPUT_LINES;
+    if !threads || !long {
+        return api::send_reply(args, output).await;
+    }
+
+    // Threads can't be started in direct messages or inside of another thread
+    let can_start_thread = args
+        .msg
+        .channel_id
+        .to_channel(&args.cx)
+        .await?
+        .guild()
+        .is_some_and(|channel| matches!(channel.kind, ChannelType::Text | ChannelType::News));
+    if !can_start_thread {
+        return api::send_reply(args, output).await;
+    }
+
+    let thread = args
+        .msg
+        .channel_id
+        .create_public_thread(&args.cx, args.msg.id, |t| {
+            t.name(format!("Output for {}", args.msg.author.name))
+                .auto_archive_duration(60)
+        })
+        .await;
+
+    match thread {
+        Ok(thread) => {
+            thread.say(&args.cx, output).await?;
+            let message = format!("Output posted in {}", thread.mention());
+            api::send_reply(args, &message).await
+        }
+        // An edited command already has a thread, or the bot can't create threads here
+        Err(e) => {
+            info!("Unable to create a thread for the output: {}", e);
+            api::send_reply(args, output).await
+        }
+    }
+}
+
+/// Collapse runs of identical lines into a single `line (×N)`, so programs printing the same
+/// thing in a loop don't run out of lines.  
+fn collapse_repeated_lines(output: &str) -> String {
+    let mut collapsed = String::new();
+    let mut lines = output.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let mut count = 1;
+        while lines.peek() == Some(&line) {
+            lines.next();
+            count += 1;
+        }
+
+        if count >= MIN_REPEATED_LINES {
+            collapsed += &format!("{} (×{})\n", line, count);
+        } else {
+            collapsed += &format!("{}\n", line).repeat(count);
+        }
+    }
+
+    if !output.ends_with('\n') {
+        collapsed.pop();
+    }
+    collapsed
+}
+
+async fn post_code(http: &HttpClient, request: &PlaygroundCode) -> reqwest::Result<PlayResult> {
+    let _slot = request_slot().await;
+    let start = Instant::now();
+    let result = http
+        .post("https://play.rust-lang.org/execute")
+        .json(request)
+        .timeout(PLAYGROUND_TIMEOUT)
+        .send()
+        .await?
+        .json()
+        .await;
+
+    metrics::record_playground_latency(start.elapsed());
+    result
 }
 
-async fn run_code(args: Arc<Args>, code: String) -> Result<String, Error> {
+async fn execute_code(
+    http: &HttpClient,
+    params: &HashMap<&'static str, String>,
+    code: String,
+    source: &str,
+) -> Result<String, Error> {
     let mut errors = String::new();
 
-    let warnings = args.params.get("warn").map(|s| &s[..]).unwrap_or("false");
-    let channel = args
-        .params
-        .get("channel")
-        .map(|s| &s[..])
-        .unwrap_or("nightly");
-    let mode = args.params.get("mode").map(|s| &s[..]).unwrap_or("debug");
-    let edition = args.params.get("edition").map(|s| &s[..]).unwrap_or("2021");
+    let warnings = params.get("warn").map(|s| &s[..]).unwrap_or("false");
+    let gist = params.get("gist").map(|s| &s[..]).unwrap_or("false");
+    let verbose = params.get("verbose").map(|s| &s[..]).unwrap_or("false") == "true";
+    let channel = params.get("channel").map(|s| &s[..]).unwrap_or("nightly");
+    let mode = params.get("mode").map(|s| &s[..]).unwrap_or("debug");
+    let edition = params.get("edition").map(|s| &s[..]).unwrap_or("2021");
+    let crate_type = params.get("crate").map(|s| &s[..]);
 
     let mut request = PlaygroundCode::new(code.clone());
 
@@ -165,57 +348,145 @@ async fn run_code(args: Arc<Args>, code: String) -> Result<String, Error> {
         Err(e) => errors += &format!("{}\n", e),
     }
 
-    if !code.contains("fn main") {
-        request.crate_type = CrateType::Library;
+    match crate_type.map(CrateType::from_str) {
+        Some(Ok(c)) => request.crate_type = c,
+        Some(Err(e)) => errors += &format!("{}\n", e),
+        None if !code.contains("fn main") => request.crate_type = CrateType::Library,
+        None => {}
     }
 
-    let message = "*Running code on playground...*";
-    api::send_reply(args.clone(), message).await?;
+    let result = match post_code(http, &request).await {
+        Ok(result) => result,
+        Err(e) if e.is_timeout() => {
+            info!("playground request timed out");
+            return Ok(format!("{}{}", errors, TIMEOUT_MESSAGE));
+        }
+        Err(e) => return Err(e.into()),
+    };
 
-    let resp = args
-        .http
-        .post("https://play.rust-lang.org/execute")
-        .json(&request)
-        .send()
-        .await?;
+    // The playground kills programs that run for too long itself
+    if !result.success && result.stderr.contains("timeout --signal=KILL") {
+        return Ok(format!("{}{}", errors, TIMEOUT_MESSAGE));
+    }
+
+    let exit_note = result.exit_note();
 
-    let result: PlayResult = resp.json().await?;
+    // Show exactly what was compiled, including the wrapper `?eval` adds
+    let source = if verbose { &code[..] } else { source };
 
-    let result = if warnings == "true" {
+    let listing = if verbose {
+        format!("```rust\n{}\n```", api::escape_code_block(code.trim_end()))
+    } else {
+        String::new()
+    };
+
+    let result = if verbose {
+        format!("stderr:\n{}\nstdout:\n{}", result.stderr, result.stdout)
+    } else if warnings == "true" {
         format!("{}\n{}", result.stderr, result.stdout)
     } else if result.success {
         result.stdout
     } else {
         result.stderr
     };
+    // Verbose output is shown exactly as the program printed it
+    let result = if verbose {
+        result
+    } else {
+        collapse_repeated_lines(&result)
+    };
+    // Programs can print backticks, which would end the code block early
+    let result = api::escape_code_block(&result);
 
     let lines = result.lines().count();
+    // Output that can't be posted isn't worth copying into a transcript either
+    let too_large = result.len() + listing.len() > MAX_OUTPUT_LEN
+        || lines + listing.lines().count() > MAX_OUTPUT_LINES;
+
+    let mut footer = error_code_links(&result);
+
+    if let Some(exit_note) = exit_note {
+        footer += &format!("\n{}", exit_note);
+    }
+
+    if code.contains("stdin()") {
+        footer += "\nNote: the playground does not support stdin, reading from it will always return no input.";
+    }
+
+    let output = result
+        .lines()
+        .fold(String::new(), |output, line| output + "// " + line + "\n");
+
+    if gist == "true" && !too_large {
+        footer += &format!(
+            "\nTranscript: {}",
+            get_playground_link(http, format!("{}\n\n{}", source, output), &request).await?
+        );
+    }
 
     Ok(
-        if result.len() + errors.len() > 1993 || lines > MAX_OUTPUT_LINES {
+        if too_large || listing.len() + result.len() + errors.len() + footer.len() > MAX_OUTPUT_LEN
+        {
+            // In verbose mode the output is kept, next to the code it came from
+            let shared = if verbose {
+                format!("{}\n\n{}", source, output)
+            } else {
+                source.to_string()
+            };
             format!(
-                "{}Output too large. Playground link: {}",
+                "{}Output too large. Playground link: {}{}",
                 errors,
-                get_playground_link(args, code, request).await?
+                get_playground_link(http, shared, &request).await?,
+                footer
             )
         } else if result.len() == 0 {
-            format!("{}compilation succeeded.", errors)
+            format!("{}{}compilation succeeded.{}", errors, listing, footer)
         } else {
-            format!("{}```\n{}```", errors, result)
+            format!("{}{}```\n{}```{}", errors, listing, result, footer)
         },
     )
 }
 
+/// Link to the explanations of the error codes (like `E0308`) found in compiler output.  
+fn error_code_links(output: &str) -> String {
+    let mut codes: Vec<&str> = Vec::new();
+
+    output
+        .split("error[")
+        .skip(1)
+        .filter_map(|rest| rest.split(']').next())
+        .filter(|code| {
+            code.len() == 5
+                && code.starts_with('E')
+                && code[1..].chars().all(|c| c.is_ascii_digit())
+        })
+        .for_each(|code| {
+            if !codes.contains(&code) && codes.len() < MAX_ERROR_CODES {
+                codes.push(code);
+            }
+        });
+
+    if codes.is_empty() {
+        String::new()
+    } else {
+        let links = codes
+            .iter()
+            .map(|code| format!("<https://doc.rust-lang.org/error_codes/{}.html>", code))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("\nError explanations: {}", links)
+    }
+}
+
 async fn get_playground_link(
-    args: Arc<Args>,
+    http: &HttpClient,
     code: String,
-    request: PlaygroundCode,
+    request: &PlaygroundCode,
 ) -> Result<String, Error> {
     let mut payload = HashMap::new();
     payload.insert("code", code);
 
-    let resp = args
-        .http
+    let resp = http
         .post("https://play.rust-lang.org/meta/gist/")
         .header(header::REFERER, "https://discord.gg/rust-lang")
         .json(&payload)
@@ -237,28 +508,344 @@ pub async fn run(args: Arc<Args>) -> Result<(), Error> {
         .map(String::from)
         .ok_or("Unable to retrieve param: query")?;
 
-    let result = run_code(args.clone(), code).await?;
-    api::send_reply(args.clone(), &result).await?;
+    if code.trim().is_empty() {
+        return err(args).await;
+    }
+
+    // Code split across several blocks, like a library and its usage, is run together
+    let blocks = code_blocks(&args.msg.content);
+    let code = match join_files(&blocks) {
+        Ok(Some(code)) => code,
+        Ok(None) if blocks.len() > 1 => blocks.join("\n"),
+        Ok(None) => code,
+        Err(e) => {
+            api::send_reply(args.clone(), e).await?;
+            return Ok(());
+        }
+    };
+
+    run_or_compile(args, code).await
+}
+
+/// The comment that names the file a code block belongs to, like `// file: src/lib.rs`.  
+const FILE_LABEL: &str = "// file:";
+
+/// Split the `// file: ...` label off the first line of a code block.  
+fn file_label(block: &str) -> Option<(&str, &str)> {
+    let block = block.trim_start();
+    let (first_line, code) = block.split_once('\n').unwrap_or((block, ""));
+    let name = first_line.trim().strip_prefix(FILE_LABEL)?.trim();
+    Some((name.trim_start_matches("src/"), code))
+}
+
+/// Put code blocks labeled `// file: src/lib.rs` and `// file: src/main.rs` into a single file,
+/// with the library inlined as the `playground` module so `main` can `use playground::...` like
+/// it would use the crate.  
+///
+/// Returns `None` when no block is labeled, and an explanation when the files can't be joined.  
+fn join_files(blocks: &[&str]) -> Result<Option<String>, &'static str> {
+    let files = blocks
+        .iter()
+        .map(|block| file_label(block))
+        .collect::<Vec<_>>();
+
+    if files.iter().all(Option::is_none) {
+        return Ok(None);
+    }
+
+    let (lib, main) = match &files[..] {
+        [Some(("lib.rs", lib)), Some(("main.rs", main))]
+        | [Some(("main.rs", main)), Some(("lib.rs", lib))] => (lib, main),
+        _ => {
+            return Err(
+                "Files can only be used as a pair of code blocks labeled `// file: src/lib.rs` and `// file: src/main.rs`.",
+            )
+        }
+    };
+
+    // Inside a module, `crate::` would point to the binary instead of the library
+    if lib.contains("crate::") {
+        return Err(
+            "`src/lib.rs` can't use `crate::` paths, since it is run as a module of `src/main.rs`. Use `self::` or `super::` paths instead.",
+        );
+    }
+
+    Ok(Some(format!(
+        "#[allow(dead_code)]\nmod playground {{\n{}\n}}\n{}",
+        lib, main
+    )))
+}
+
+/// Move inner attributes, like `#![feature(...)]` or `#![no_std]`, to the top of the code.  
+///
+/// They are only allowed at the start of a crate, which they aren't when code blocks are joined
+/// or when they come after a `use`.  
+fn hoist_inner_attributes(code: &str) -> String {
+    let mut attributes = String::new();
+    let mut rest = String::new();
+    let mut open_brackets = 0;
+
+    for line in code.lines() {
+        if open_brackets == 0 && !line.trim_start().starts_with("#![") {
+            rest += line;
+            rest += "\n";
+            continue;
+        }
+
+        // An attribute can span several lines, like a long `#![allow(...)]`
+        open_brackets += line.matches('[').count() as i64 - line.matches(']').count() as i64;
+        open_brackets = open_brackets.max(0);
+        attributes += line.trim_start();
+        attributes += "\n";
+    }
+
+    attributes + &rest
+}
+
+/// Run the code, or show its assembly with godbolt when the `output` param is `asm`.  
+async fn run_or_compile(args: Arc<Args>, code: String) -> Result<(), Error> {
+    let code = if code.contains("#![") {
+        hoist_inner_attributes(&code)
+    } else {
+        code
+    };
+
+    match args.params.get("output").map(|s| &s[..]).unwrap_or("run") {
+        "run" => {
+            let result = run_code(args.clone(), code.clone(), &code).await?;
+            send_output(args.clone(), &result).await?;
+            Ok(())
+        }
+        "asm" => {
+            let compiler = match args.params.get("channel").map(|s| &s[..]) {
+                Some("beta") => "beta",
+                // godbolt only tracks beta and nightly by name
+                _ => "nightly",
+            };
+
+            let mut user_arguments = format!(
+                "--edition={}",
+                args.params.get("edition").map(|s| &s[..]).unwrap_or("2021")
+            );
+            // Like on the playground, code is only optimized when asked for with `mode=release`
+            if args.params.get("mode").map(|s| &s[..]) == Some("release") {
+                user_arguments += " -O";
+            }
+
+            godbolt::send_output(args.clone(), &code, false, compiler, user_arguments).await
+        }
+        output => {
+            let message = format!("unknown output `{}`, use run or asm", output);
+            api::send_reply(args.clone(), &message).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Run the code block of the message that `?play` replied to.  
+pub async fn run_reply(args: Arc<Args>) -> Result<(), Error> {
+    match referenced_code(&args, false) {
+        Some(code) => run_or_compile(args, code).await,
+        None => err(args).await,
+    }
+}
+
+/// Extract the contents of the fenced code blocks in a message, from top to bottom.  
+fn code_blocks(mut content: &str) -> Vec<&str> {
+    let mut blocks = Vec::new();
+
+    while let Some((block, rest)) = content
+        .split_once("```")
+        .and_then(|(_, rest)| rest.split_once("```"))
+    {
+        // Drop the language tag of the code block, like ```rust
+        let code = match block.split_once('\n') {
+            Some((tag, code)) if !tag.contains(char::is_whitespace) => code,
+            _ => block,
+        };
+
+        if !code.trim().is_empty() {
+            blocks.push(code);
+        }
+        content = rest;
+    }
+
+    blocks
+}
+
+/// Extract the code from the message the command replied to, if there is one.  
+///
+/// `?play` runs all of the code blocks together, while `?eval` takes the first code block or
+/// inline code.  
+fn referenced_code(args: &Args, eval: bool) -> Option<String> {
+    let content = &args.msg.referenced_message.as_ref()?.content;
+    let blocks = code_blocks(content);
+
+    if !blocks.is_empty() {
+        if eval {
+            Some(blocks[0].to_string())
+        } else {
+            Some(blocks.join("\n"))
+        }
+    } else if eval {
+        let (_, rest) = content.split_once('`')?;
+        let (code, _) = rest.split_once('`')?;
+        Some(code.to_string()).filter(|code| !code.trim().is_empty())
+    } else {
+        None
+    }
+}
+
+/// Handle the `/play` slash command.  
+pub async fn slash_run(
+    cx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    http: &HttpClient,
+) -> Result<(), Error> {
+    let code = slash::option(interaction, "code")
+        .map(String::from)
+        .ok_or("Unable to retrieve option: code")?;
+
+    let params = [
+        "mode", "edition", "channel", "crate", "warn", "gist", "verbose",
+    ]
+    .iter()
+    .filter_map(|name| slash::option(interaction, name).map(|value| (*name, value.to_string())))
+    .collect::<HashMap<_, _>>();
+
+    let result = execute_code(http, &params, code.clone(), &code).await?;
+    slash::respond(cx, interaction, &result).await?;
     Ok(())
 }
 
 pub async fn help(args: Arc<Args>, name: &str) -> Result<(), Error> {
+    // `?eval` always wraps the code in `fn main`, so only `?play` can choose the crate type,
+    // and only `?eval` prints a result
+    let (crate_arg, crate_help, flags_help) = if name == "play" {
+        (
+            " crate={} output={}",
+            "\n    \tcrate: bin, lib (default: bin if the code contains `fn main`, lib otherwise)\
+             \n    \toutput: run, asm to show the assembly from godbolt.org instead (default: run)",
+            "\nCommand line style flags like `--release` or `--edition 2018` work too.",
+        )
+    } else {
+        (
+            " fmt={}",
+            "\n    \tfmt: the format string the result is printed with, like `\"{:#?}\"` (default: `{:?}`)",
+            "",
+        )
+    };
+
     let message = format!(
         "Compile and run rust code. All code is executed on https://play.rust-lang.org.
-```?{} mode={{}} channel={{}} edition={{}} warn={{}} ``\u{200B}`code``\u{200B}` ```
+```?{} mode={{}} channel={{}} edition={{}}{} warn={{}} gist={{}} verbose={{}} ``\u{200B}`code``\u{200B}` ```
 Optional arguments:
     \tmode: debug, release (default: debug)
     \tchannel: stable, beta, nightly (default: nightly)
-    \tedition: 2015, 2018, 2021 (default: 2021)
+    \tedition: 2015, 2018, 2021 (default: 2021){}
     \twarn: boolean flag to enable compilation warnings
+    \tgist: boolean flag to share the code and its output as a playground link
+    \tverbose: boolean flag to show the exact code that was compiled along with all of its output{}
+Reply to a message with `?{}` to run the code block in that message instead.
+Multiple code blocks in one `?play` message are run together, from top to bottom.
+A pair of blocks starting with `// file: src/lib.rs` and `// file: src/main.rs` runs as a library \
+and a binary that uses it with `use playground::...`.
+Programs that panic or exit with a non-zero status are noted below the output.
+Repeated lines of output are shown once with a count, like `hi (×1000)`, unless `verbose` is set.
+The top 100 crates from crates.io are available, see https://play.rust-lang.org/help#features-crates
     ",
-        name
+        name, crate_arg, crate_help, flags_help, name
     );
 
     api::send_reply(args.clone(), &message).await?;
     Ok(())
 }
 
+/// Translate command line style flags, like `--release` or `--edition 2018`, into the params
+/// `?play` takes.  Returns `None` if anything isn't a recognized flag or `key=value` param.  
+fn flag_params(flags: &str) -> Option<HashMap<&'static str, String>> {
+    let mut params = HashMap::new();
+    let mut words = flags.split_whitespace();
+
+    while let Some(word) = words.next() {
+        let (flag, value) = match word.split_once('=') {
+            Some((flag, value)) => (flag.trim_start_matches('-'), Some(value.to_string())),
+            None => (word.trim_start_matches('-'), None),
+        };
+
+        let (key, value) = match (flag, value) {
+            ("debug" | "release", None) => ("mode", flag.to_string()),
+            ("stable" | "beta" | "nightly", None) => ("channel", flag.to_string()),
+            ("warn", None) => ("warn", String::from("true")),
+            ("gist", None) => ("gist", String::from("true")),
+            ("verbose", None) => ("verbose", String::from("true")),
+            ("asm", None) => ("output", String::from("asm")),
+            ("warn", Some(value)) => ("warn", value),
+            ("gist", Some(value)) => ("gist", value),
+            ("verbose", Some(value)) => ("verbose", value),
+            (_, value) => {
+                let key = match flag {
+                    "mode" => "mode",
+                    "edition" => "edition",
+                    "channel" => "channel",
+                    "crate" | "crate-type" => "crate",
+                    "output" => "output",
+                    _ => return None,
+                };
+                // Both `--edition 2018` and `--edition=2018` are accepted
+                (key, value.or_else(|| words.next().map(String::from))?)
+            }
+        };
+
+        params.insert(key, value);
+    }
+
+    Some(params)
+}
+
+/// Whether the first code block in `content` starts on its own line, like ```` ```rust\n ````,
+/// rather than right after the fence, where a language tag would be read as code.  
+fn is_fenced_block(content: &str) -> bool {
+    content
+        .split("```")
+        .nth(1)
+        .and_then(|block| block.split_once('\n'))
+        .is_some_and(|(tag, _)| !tag.contains(char::is_whitespace))
+}
+
+/// Run `?play` messages that use command line style flags, like `?play --release`.  
+pub async fn run_with_flags(args: Arc<Args>) -> Result<(), Error> {
+    let content = &args.msg.content;
+    let flags = content
+        .trim_start_matches(PREFIX)
+        .trim_start_matches("play")
+        .split("```")
+        .next()
+        .unwrap_or("");
+
+    // Anything else reaching this command is a malformed `?play`, which gets the usage hint
+    let has_flags = flags.split_whitespace().any(|word| word.starts_with("--"));
+    let blocks = code_blocks(content);
+    let params =
+        flag_params(flags).filter(|_| has_flags && !blocks.is_empty() && is_fenced_block(content));
+
+    match params {
+        Some(mut params) => {
+            params.insert("code", blocks[0].to_string());
+            let args = Arc::new(Args {
+                cx: args.cx.clone(),
+                msg: args.msg.clone(),
+                params,
+                http: args.http.clone(),
+                db: args.db.clone(),
+                usage: args.usage.clone(),
+            });
+            run(args).await
+        }
+        None => err(args).await,
+    }
+}
+
 pub async fn err(args: Arc<Args>) -> Result<(), Error> {
     let message = "Missing code block. Please use the following markdown:
 \\`\\`\\`rust
@@ -277,22 +864,190 @@ pub async fn eval(args: Arc<Args>) -> Result<(), Error> {
         .map(String::from)
         .ok_or("Unable to retrieve param: query")?;
 
+    // Unbalanced backticks leave the extra ones in the extracted code
+    if code.trim().is_empty() || code.starts_with('`') || code.ends_with('`') {
+        return eval_err(args).await;
+    }
+
+    eval_code(args, code).await
+}
+
+/// Evaluate the code block of the message that `?eval` replied to.  
+pub async fn eval_reply(args: Arc<Args>) -> Result<(), Error> {
+    match referenced_code(&args, true) {
+        Some(code) => eval_code(args, code).await,
+        None => eval_err(args).await,
+    }
+}
+
+async fn eval_code(args: Arc<Args>, code: String) -> Result<(), Error> {
     if code.contains("fn main") {
         api::send_reply(
             args.clone(),
             "code passed to ?eval should not contain `fn main`",
         )
         .await?;
+    } else if code
+        .lines()
+        .any(|line| line.trim_start().starts_with("#!["))
+    {
+        // The expression ends up inside of `fn main`, where inner attributes aren't allowed
+        api::send_reply(
+            args.clone(),
+            "code passed to ?eval can't use inner attributes like `#![feature(...)]`, use ?play with a `fn main` instead",
+        )
+        .await?;
+    } else {
+        let fmt = args.params.get("fmt").map(|s| &s[..]).unwrap_or("{:?}");
+        if let Err(e) = check_eval_format(fmt) {
+            api::send_reply(args.clone(), e).await?;
+            return Ok(());
+        }
+
+        let (wrapped, try_operator) = eval_wrapper(&code, fmt);
+
+        // Links to the output share what the user wrote rather than the wrapper
+        let mut result = run_code(args.clone(), wrapped, &code).await?;
+        if try_operator && result.contains("the `?` operator can only be used on `Option`s") {
+            result +=
+                "\nNote: expressions using `?` are evaluated in a function returning `Result`, \
+                turn `Option`s into a `Result` first, like `.ok_or(\"none\")?`";
+        }
+        send_output(args.clone(), &result).await?;
+    }
+
+    Ok(())
+}
+
+/// Wrap an expression passed to `?eval` in a `fn main` printing it with `fmt`.  
+///
+/// Also returns whether the expression uses `?`, in which case `main` returns a `Result`.  
+fn eval_wrapper(code: &str, fmt: &str) -> (String, bool) {
+    let (crates, expression) = hoist_extern_crates(code);
+    let try_operator = uses_try_operator(expression);
+    let wrapped = if try_operator {
+        format!(
+            "{}fn main() -> Result<(), Box<dyn std::error::Error>> {{ println!(\"{}\",{{ {} \n}}); Ok(()) }}",
+            crates, fmt, expression
+        )
     } else {
-        let code = format!("fn main(){{ println!(\"{{:?}}\",{{ {} \n}}); }}", code);
+        format!(
+            "{}fn main(){{ println!(\"{}\",{{ {} \n}}); }}",
+            crates, fmt, expression
+        )
+    };
+
+    (wrapped, try_operator)
+}
+
+/// Check that a format string passed to `?eval` prints the result exactly once, like `{:#?}`.  
+///
+/// It's pasted into a string literal, so it can't contain quotes or escapes, and it can't
+/// refer to any other argument by name, position or `$`/`*` width.  
+fn check_eval_format(fmt: &str) -> Result<(), &'static str> {
+    if fmt.contains(['"', '\\', '\n']) {
+        return Err("The format string can't contain quotes, backslashes or newlines.");
+    }
+
+    let mut placeholders = 0;
+    let mut chars = fmt.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+            }
+            '{' => {
+                let placeholder = chars
+                    .by_ref()
+                    .take_while(|ch| *ch != '}')
+                    .collect::<String>();
+                if !placeholder.is_empty() && !placeholder.starts_with(':')
+                    || placeholder.contains(['{', '$', '*'])
+                {
+                    return Err("The placeholder in the format string can only have a format spec, like `{:#?}` or `{:>8}`.");
+                }
+                placeholders += 1;
+            }
+            '}' => return Err("The format string has an unmatched `}`, use `}}` to print one."),
+            _ => {}
+        }
+    }
 
-        let result = run_code(args.clone(), code).await?;
-        api::send_reply(args.clone(), &result).await?;
+    if placeholders != 1 {
+        return Err("The format string needs exactly one placeholder, like `{:#?}`.");
     }
 
     Ok(())
 }
 
+/// Check whether `code` uses the `?` operator, ignoring question marks inside of string and
+/// character literals or comments.  
+fn uses_try_operator(code: &str) -> bool {
+    let mut chars = code.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '?' => return true,
+            '"' => {
+                while let Some(ch) = chars.next() {
+                    match ch {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            // A character literal like '?' or '\'', anything else is a lifetime
+            '\'' => {
+                let mut lookahead = chars.clone();
+                let literal_len = match (lookahead.next(), lookahead.next()) {
+                    (Some('\\'), _) => lookahead.position(|ch| ch == '\'').map(|i| i + 3),
+                    (Some(_), Some('\'')) => Some(2),
+                    _ => None,
+                };
+                if let Some(len) = literal_len {
+                    chars.nth(len - 1);
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                chars.find(|ch| *ch == '\n');
+            }
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Split leading `extern crate` declarations off of an expression passed to `?eval` so
+/// they can be placed at the crate root instead of inside of `fn main`.  
+fn hoist_extern_crates(mut code: &str) -> (String, &str) {
+    let mut crates = String::new();
+
+    loop {
+        let trimmed = code.trim_start();
+        match trimmed
+            .strip_prefix("extern crate")
+            .and_then(|_| trimmed.find(';'))
+        {
+            Some(end) => {
+                crates += &trimmed[..=end];
+                crates += "\n";
+                code = &trimmed[end + 1..];
+            }
+            None => break,
+        }
+    }
+
+    (crates, code)
+}
+
 pub async fn eval_err(args: Arc<Args>) -> Result<(), Error> {
     let message = "Missing code block. Please use the following markdown:
     \\`code here\\`
@@ -305,3 +1060,61 @@ pub async fn eval_err(args: Arc<Args>) -> Result<(), Error> {
     api::send_reply(args.clone(), message).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_need_a_fenced_code_block() {
+        assert!(is_fenced_block("?play --release ```rust\nfn main() {}```"));
+        assert!(is_fenced_block("?play --release ```\nfn main() {}```"));
+        assert!(!is_fenced_block("?play --release ```rust fn main(){}```"));
+        assert!(!is_fenced_block("?play --release ```fn main(){}```"));
+        assert!(!is_fenced_block("?play --release"));
+    }
+
+    #[test]
+    fn flags_are_translated_to_params() {
+        let params = flag_params(" --release --edition 2018 --warn ").unwrap();
+        assert_eq!(params["mode"], "release");
+        assert_eq!(params["edition"], "2018");
+        assert_eq!(params["warn"], "true");
+
+        assert!(flag_params("--unknown").is_none());
+    }
+
+    #[test]
+    fn extern_crates_are_hoisted() {
+        let (crates, expression) =
+            hoist_extern_crates("extern crate rand; extern crate regex;\nrand::random::<u8>()");
+        assert_eq!(crates, "extern crate rand;\nextern crate regex;\n");
+        assert_eq!(expression, "\nrand::random::<u8>()");
+
+        let (crates, expression) = hoist_extern_crates("1 + 1");
+        assert_eq!(crates, "");
+        assert_eq!(expression, "1 + 1");
+
+        // Only leading declarations are hoisted
+        let (crates, expression) = hoist_extern_crates("let a = 1; extern crate rand; a");
+        assert_eq!(crates, "");
+        assert_eq!(expression, "let a = 1; extern crate rand; a");
+    }
+
+    #[test]
+    fn eval_wraps_the_expression_in_main() {
+        let (wrapped, try_operator) = eval_wrapper("extern crate rand;\n1 + 1", "{:#?}");
+        assert!(!try_operator);
+        assert_eq!(
+            wrapped,
+            "extern crate rand;\nfn main(){ println!(\"{:#?}\",{ \n1 + 1 \n}); }"
+        );
+
+        let (wrapped, try_operator) = eval_wrapper("\"1\".parse::<u8>()?", "{:?}");
+        assert!(try_operator);
+        assert_eq!(
+            wrapped,
+            "fn main() -> Result<(), Box<dyn std::error::Error>> { println!(\"{:?}\",{ \"1\".parse::<u8>()? \n}); Ok(()) }"
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use crate::Error;
+use serenity::{model::prelude::*, prelude::*};
+
+/// The configured statuses and the index of the next one to show.  
+pub struct Statuses;
+
+impl TypeMapKey for Statuses {
+    type Value = (Vec<String>, usize);
+}
+
+/// Split the `STATUS` config value into the statuses to rotate through.  
+pub fn parse_statuses(status: &str) -> Vec<String> {
+    status
+        .split(';')
+        .map(str::trim)
+        .filter(|status| !status.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Show the next status as a "Watching" activity.  
+pub async fn rotate_status(cx: &Context) -> Result<(), Error> {
+    let status = {
+        let mut data = cx.data.write().await;
+        let (statuses, next) = data.get_mut::<Statuses>().unwrap();
+        if statuses.is_empty() {
+            return Ok(());
+        }
+
+        let status = statuses[*next % statuses.len()].clone();
+        *next = (*next + 1) % statuses.len();
+        status
+    };
+
+    cx.set_activity(Activity::watching(status)).await;
+    Ok(())
+}
@@ -0,0 +1,25 @@
+use std::sync::OnceLock;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// How many requests to the playground and godbolt can be in flight at once.  
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+
+static REQUEST_SLOTS: OnceLock<Semaphore> = OnceLock::new();
+
+fn request_slots() -> &'static Semaphore {
+    REQUEST_SLOTS.get_or_init(|| Semaphore::new(MAX_CONCURRENT_REQUESTS))
+}
+
+/// Wait until fewer than `MAX_CONCURRENT_REQUESTS` requests are running on the playground or
+/// godbolt, holding one of the slots until the returned permit is dropped.  
+pub async fn request_slot() -> SemaphorePermit<'static> {
+    request_slots()
+        .acquire()
+        .await
+        .expect("the request semaphore is never closed")
+}
+
+/// Whether a new request would have to wait for a slot, to tell the user it is queued.  
+pub fn all_taken() -> bool {
+    request_slots().available_permits() == 0
+}
@@ -0,0 +1,239 @@
+use crate::{
+    api,
+    commands::{param_error, Args},
+    Error,
+};
+use serenity::{model::prelude::*, utils::parse_role};
+use std::{str::FromStr, sync::Arc};
+use tracing::info;
+
+/// The role a self-assignable role name stands for in the guild of the command.  
+async fn self_role(args: &Args, guild_id: GuildId, name: &str) -> Result<Option<RoleId>, Error> {
+    let role: Option<(String,)> = sqlx::query_as(
+        "select role from self_roles where lower(name) = lower($1) and guild_id = $2",
+    )
+    .bind(name)
+    .bind(guild_id.to_string())
+    .fetch_optional(&*args.db)
+    .await?;
+
+    match role {
+        Some((role,)) => Ok(Some(RoleId::from(u64::from_str(&role)?))),
+        None => Ok(None),
+    }
+}
+
+/// List the roles members can give themselves with `?iam`.  
+pub async fn list(args: Arc<Args>) -> Result<(), Error> {
+    let guild_id = api::guild_id(args.clone()).await?;
+
+    let names: Vec<(String,)> =
+        sqlx::query_as("select name from self_roles where guild_id = $1 order by name")
+            .bind(guild_id.to_string())
+            .fetch_all(&*args.db)
+            .await?;
+
+    let message = if names.is_empty() {
+        String::from("There are no roles you can give yourself in this server.")
+    } else {
+        let names = names
+            .iter()
+            .map(|(name,)| format!("`{}`", name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!(
+            "Roles you can give yourself with `?iam {{role}}`: {}",
+            names
+        )
+    };
+
+    api::send_reply(args.clone(), &message).await?;
+    Ok(())
+}
+
+/// Let members give themselves `role` under `name`, like `?roles add news @News`.  
+pub async fn add(args: Arc<Args>) -> Result<(), Error> {
+    let name = args
+        .params
+        .get("name")
+        .ok_or("Unable to retrieve param: name")?;
+    let role = args
+        .params
+        .get("role")
+        .ok_or("Unable to retrieve param: role")?;
+
+    let role_id = parse_role(role)
+        .or_else(|| role.parse::<u64>().ok())
+        .ok_or_else(|| param_error(format!("`{}` isn't a role mention or id", role)))?;
+    let guild_id = api::guild_id(args.clone()).await?;
+
+    if let Some(refusal) = role_refusal(&args, guild_id, RoleId(role_id)).await? {
+        api::send_reply(args.clone(), refusal).await?;
+        return Ok(());
+    }
+
+    info!("Making role {} self-assignable as {}", role_id, name);
+    sqlx::query(
+        "insert into self_roles(name, role, guild_id) values ($1, $2, $3)
+            on conflict (name, guild_id) do update set role = $2",
+    )
+    .bind(name.to_lowercase())
+    .bind(role_id.to_string())
+    .bind(guild_id.to_string())
+    .execute(&*args.db)
+    .await?;
+
+    args.msg.react(&args.cx, '✅').await?;
+    Ok(())
+}
+
+/// Why `role` can't be made self-assignable by the author of the command, if it can't.  
+///
+/// Roles with moderation permissions are never self-assignable, and mods can only hand out
+/// roles below their own highest role unless they own the guild.  
+async fn role_refusal(
+    args: &Args,
+    guild_id: GuildId,
+    role_id: RoleId,
+) -> Result<Option<&'static str>, Error> {
+    let roles = guild_id.roles(&args.cx).await?;
+    let role = match roles.get(&role_id) {
+        Some(role) => role,
+        None => return Ok(Some("That role doesn't exist in this server.")),
+    };
+
+    let privileged = Permissions::ADMINISTRATOR
+        | Permissions::MANAGE_CHANNELS
+        | Permissions::MANAGE_EMOJIS_AND_STICKERS
+        | Permissions::MANAGE_EVENTS
+        | Permissions::MANAGE_GUILD
+        | Permissions::MANAGE_MESSAGES
+        | Permissions::MANAGE_NICKNAMES
+        | Permissions::MANAGE_ROLES
+        | Permissions::MANAGE_THREADS
+        | Permissions::MANAGE_WEBHOOKS;
+
+    if role.permissions.intersects(privileged) {
+        return Ok(Some(
+            "Roles with administrator or manage permissions can't be self-assignable.",
+        ));
+    }
+
+    let is_owner = args
+        .cx
+        .cache
+        .guild_field(guild_id, |guild| guild.owner_id == args.msg.author.id)
+        .unwrap_or(false);
+
+    if !is_owner {
+        let author = guild_id.member(&args.cx, args.msg.author.id).await?;
+        let highest = author
+            .roles
+            .iter()
+            .filter_map(|id| roles.get(id))
+            .map(|role| role.position)
+            .max()
+            .unwrap_or(0);
+
+        if role.position >= highest {
+            return Ok(Some(
+                "You can only make roles below your highest role self-assignable.",
+            ));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Stop members from giving themselves the role `name`.  Members who have it keep it.  
+pub async fn remove(args: Arc<Args>) -> Result<(), Error> {
+    let name = args
+        .params
+        .get("name")
+        .ok_or("Unable to retrieve param: name")?;
+    let guild_id = api::guild_id(args.clone()).await?;
+
+    let removed =
+        sqlx::query("delete from self_roles where lower(name) = lower($1) and guild_id = $2")
+            .bind(name)
+            .bind(guild_id.to_string())
+            .execute(&*args.db)
+            .await?
+            .rows_affected();
+
+    if removed == 0 {
+        let message = format!("`{}` is not a role you can give yourself", name);
+        api::send_reply(args.clone(), &message).await?;
+        return Ok(());
+    }
+
+    info!("Role {} is no longer self-assignable", name);
+    args.msg.react(&args.cx, '✅').await?;
+    Ok(())
+}
+
+async fn set_self_role(args: Arc<Args>, assign: bool) -> Result<(), Error> {
+    let name = args
+        .params
+        .get("role")
+        .ok_or("Unable to retrieve param: role")?;
+    let guild_id = api::guild_id(args.clone()).await?;
+
+    let role_id = match self_role(&args, guild_id, name).await? {
+        Some(role_id) => role_id,
+        None => {
+            let message = format!(
+                "`{}` is not a role you can give yourself, see `?roles` for the ones you can",
+                name
+            );
+            api::send_reply(args.clone(), &message).await?;
+            return Ok(());
+        }
+    };
+
+    let mut member = guild_id.member(&args.cx, args.msg.author.id).await?;
+    if assign {
+        info!("Assigning role {} to {}", role_id, member.user.id);
+        member.add_role(&args.cx, role_id).await?;
+    } else {
+        info!("Removing role {} from {}", role_id, member.user.id);
+        member.remove_role(&args.cx, role_id).await?;
+    }
+
+    args.msg.react(&args.cx, '✅').await?;
+    Ok(())
+}
+
+/// Give yourself a role listed by `?roles`.  
+pub async fn iam(args: Arc<Args>) -> Result<(), Error> {
+    set_self_role(args, true).await
+}
+
+/// Remove a role listed by `?roles` from yourself.  
+pub async fn iamnot(args: Arc<Args>) -> Result<(), Error> {
+    set_self_role(args, false).await
+}
+
+/// Print the help message
+pub async fn help(args: Arc<Args>) -> Result<(), Error> {
+    let help_string = "
+Give yourself roles, like the one for announcements
+```
+?roles                          List the roles you can give yourself.
+?iam {role}                     Give yourself a role.
+?iamnot {role}                  Remove a role from yourself.
+?roles add {name} {role}        Let members give themselves a role under a name.
+                                The role can't have administrator or manage
+                                permissions, and has to be below your highest role.
+                                Limited to mods.
+?roles remove {name}            Stop members from giving themselves a role.
+                                Limited to mods.
+```
+**Example:**
+```
+?roles add announcements @Announcements
+?iam announcements
+```";
+    api::send_reply(args.clone(), help_string).await?;
+    Ok(())
+}
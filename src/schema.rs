@@ -15,6 +15,7 @@ table! {
         name -> Text,
         message -> Text,
         channel -> Text,
+        guild_id -> Text,
     }
 }
 
@@ -23,6 +24,35 @@ table! {
         id -> Int4,
         role -> Text,
         name -> Text,
+        guild_id -> Text,
+    }
+}
+
+table! {
+    settings (id) {
+        id -> Int4,
+        key -> Text,
+        value -> Text,
+    }
+}
+
+table! {
+    self_roles (id) {
+        id -> Int4,
+        name -> Text,
+        role -> Text,
+        guild_id -> Text,
+    }
+}
+
+table! {
+    tag_history (id) {
+        id -> Int4,
+        key -> Text,
+        old_value -> Nullable<Text>,
+        new_value -> Nullable<Text>,
+        editor_id -> Text,
+        edited_at -> Timestamp,
     }
 }
 
@@ -31,6 +61,7 @@ table! {
         id -> Int4,
         key -> Text,
         value -> Text,
+        locked -> Bool,
     }
 }
 
@@ -42,4 +73,13 @@ table! {
     }
 }
 
-allow_tables_to_appear_in_same_query!(bans, messages, roles, tags, users,);
+allow_tables_to_appear_in_same_query!(
+    bans,
+    messages,
+    roles,
+    self_roles,
+    settings,
+    tag_history,
+    tags,
+    users,
+);
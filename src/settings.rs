@@ -0,0 +1,345 @@
+use crate::{
+    api,
+    commands::{Args, PREFIX},
+    Error,
+};
+use serenity::utils::parse_role;
+use sqlx::postgres::PgPool;
+use std::{collections::HashMap, str::FromStr, sync::Arc};
+use tracing::info;
+
+/// Retrieve the value of a setting.  
+pub async fn get(db: &PgPool, key: &str) -> Result<Option<String>, Error> {
+    let row: Option<(String,)> = sqlx::query_as("select value from settings where key = $1")
+        .bind(key)
+        .fetch_optional(db)
+        .await?;
+
+    Ok(row.map(|(value,)| value))
+}
+
+/// Retrieve a setting parsed as `T`, falling back to `default` when it is not set.  
+pub async fn get_or<T>(db: &PgPool, key: &str, default: T) -> Result<T, Error>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match get(db, key).await? {
+        Some(value) => Ok(T::from_str(&value)?),
+        None => Ok(default),
+    }
+}
+
+/// Create or update a setting.  
+pub async fn set(db: &PgPool, key: &str, value: &str) -> Result<(), Error> {
+    info!("Updating setting {}", key);
+    sqlx::query(
+        "insert into settings(key, value) values ($1, $2)
+            on conflict (key) do update set value = $2",
+    )
+    .bind(key)
+    .bind(value)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Reply with the value of a setting.  
+pub async fn get_command(args: Arc<Args>) -> Result<(), Error> {
+    let key = args
+        .params
+        .get("key")
+        .ok_or("Unable to retrieve param: key")?;
+
+    if let Some(value) = get(&args.db, key).await? {
+        api::send_reply(args.clone(), &format!("`{}` is set to `{}`", key, value)).await?;
+    } else {
+        api::send_reply(args.clone(), &format!("`{}` is not set", key)).await?;
+    }
+
+    Ok(())
+}
+
+/// Change the value of a setting.  
+pub async fn set_command(args: Arc<Args>) -> Result<(), Error> {
+    let key = args
+        .params
+        .get("key")
+        .ok_or("Unable to retrieve param: key")?;
+
+    let value = args
+        .params
+        .get("value")
+        .ok_or("Unable to retrieve param: value")?;
+
+    set(&args.db, key, value).await?;
+    args.msg.react(&args.cx, '✅').await?;
+
+    Ok(())
+}
+
+/// Use a different role for `mod`, `talk` or `wg_and_teams` in the current guild.  
+pub async fn set_role(args: Arc<Args>) -> Result<(), Error> {
+    let name = args
+        .params
+        .get("name")
+        .ok_or("Unable to retrieve param: name")?;
+
+    let role = args
+        .params
+        .get("role")
+        .ok_or("Unable to retrieve param: role")?;
+
+    if !["mod", "talk", "wg_and_teams"].contains(&name.as_str()) {
+        api::send_reply(
+            args.clone(),
+            &format!("`{}` is not a role, use mod, talk or wg_and_teams", name),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let role_id = parse_role(role)
+        .or_else(|| role.parse::<u64>().ok())
+        .ok_or("unable to retrieve role id")?;
+    let guild_id = api::guild_id(args.clone()).await?;
+
+    info!("Setting {} role for guild {}", name, guild_id);
+    sqlx::query(
+        "insert into roles(role, name, guild_id) values ($1, $2, $3)
+            on conflict (name, guild_id) do update set role = $1",
+    )
+    .bind(role_id.to_string())
+    .bind(name)
+    .bind(guild_id.to_string())
+    .execute(&*args.db)
+    .await?;
+
+    args.msg.react(&args.cx, '✅').await?;
+
+    Ok(())
+}
+
+const DISABLED_COMMANDS_SETTING: &str = "disabled_commands";
+const CONFIRM_COMMANDS_SETTING: &str = "confirm_commands";
+
+/// The name of a command as it is disabled, like `play` for `?play`.  
+pub fn command_name(command: &str) -> String {
+    command
+        .split_whitespace()
+        .next()
+        .unwrap_or("")
+        .trim_start_matches('?')
+        .to_lowercase()
+}
+
+async fn command_list(db: &PgPool, key: &str) -> Result<Vec<String>, Error> {
+    Ok(get(db, key)
+        .await?
+        .map(|value| value.split_whitespace().map(command_name).collect())
+        .unwrap_or_default())
+}
+
+async fn disabled_commands(db: &PgPool) -> Result<Vec<String>, Error> {
+    command_list(db, DISABLED_COMMANDS_SETTING).await
+}
+
+/// Check whether the command with `name` has been disabled with `?disable`.  
+pub async fn is_disabled(db: &PgPool, name: &str) -> Result<bool, Error> {
+    Ok(disabled_commands(db).await?.iter().any(|n| n == name))
+}
+
+/// Check whether the command with `name` is listed in the `confirm_commands` setting, like
+/// `purge ban`, and has to be confirmed with a reaction before it runs.  
+pub async fn needs_confirmation(db: &PgPool, name: &str) -> Result<bool, Error> {
+    Ok(command_list(db, CONFIRM_COMMANDS_SETTING)
+        .await?
+        .iter()
+        .any(|n| n == name))
+}
+
+async fn set_disabled(args: Arc<Args>, disabled: bool) -> Result<(), Error> {
+    let name = command_name(
+        args.params
+            .get("command")
+            .ok_or("Unable to retrieve param: command")?,
+    );
+
+    if ["enable", "disable"].contains(&name.as_str()) {
+        api::send_reply(args.clone(), "`?enable` and `?disable` can't be disabled").await?;
+        return Ok(());
+    }
+
+    let mut commands = disabled_commands(&args.db).await?;
+    commands.retain(|n| *n != name);
+    if disabled {
+        commands.push(name.clone());
+    }
+
+    info!(
+        "{} command {}",
+        if disabled { "Disabling" } else { "Enabling" },
+        name
+    );
+    set(&args.db, DISABLED_COMMANDS_SETTING, &commands.join(" ")).await?;
+    args.msg.react(&args.cx, '✅').await?;
+
+    Ok(())
+}
+
+/// Stop a command from running until it is enabled again.  
+pub async fn disable(args: Arc<Args>) -> Result<(), Error> {
+    set_disabled(args, true).await
+}
+
+/// Let a command disabled with `?disable` run again.  
+pub async fn enable(args: Arc<Args>) -> Result<(), Error> {
+    set_disabled(args, false).await
+}
+
+const COMMAND_ALIASES_SETTING: &str = "command_aliases";
+
+/// The aliases set with `?alias`, stored as `short=command` pairs like `p=play c=crate`.  
+async fn aliases(db: &PgPool) -> Result<HashMap<String, String>, Error> {
+    Ok(get(db, COMMAND_ALIASES_SETTING)
+        .await?
+        .unwrap_or_default()
+        .split_whitespace()
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(short, command)| (short.to_string(), command.to_string()))
+        .collect())
+}
+
+async fn set_aliases(db: &PgPool, aliases: &HashMap<String, String>) -> Result<(), Error> {
+    let mut pairs = aliases
+        .iter()
+        .map(|(short, command)| format!("{}={}", short, command))
+        .collect::<Vec<_>>();
+    pairs.sort();
+    set(db, COMMAND_ALIASES_SETTING, &pairs.join(" ")).await
+}
+
+/// Replace the alias the message starts with, like `?p`, with the command it stands for.  
+///
+/// Only the whole first word is looked up, so `?pl` doesn't expand an alias `p`.  
+pub async fn expand_alias(db: &PgPool, content: &str) -> Result<Option<String>, Error> {
+    let rest = match content.strip_prefix(PREFIX) {
+        Some(rest) => rest,
+        None => return Ok(None),
+    };
+
+    // A code block can follow the command without a space, like `?p```rust`
+    let end = rest
+        .find(|c: char| c.is_whitespace() || c == '`')
+        .unwrap_or(rest.len());
+    let (short, rest) = rest.split_at(end);
+
+    Ok(aliases(db)
+        .await?
+        .remove(short)
+        .map(|command| format!("{}{}{}", PREFIX, command, rest)))
+}
+
+/// Make `short` run the command `full`, like `?alias p play`.  
+pub async fn alias(args: Arc<Args>) -> Result<(), Error> {
+    let short = args
+        .params
+        .get("short")
+        .ok_or("Unable to retrieve param: short")?;
+    let full = args
+        .params
+        .get("full")
+        .ok_or("Unable to retrieve param: full")?;
+
+    let short = short.trim_start_matches(PREFIX);
+    let full = full.trim_start_matches(PREFIX);
+
+    if short.is_empty() || full.is_empty() || short.contains(['=', '`']) || full.contains('=') {
+        api::send_reply(
+            args.clone(),
+            "Aliases and commands can't be empty or contain `=`",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let mut aliases = aliases(&args.db).await?;
+    aliases.insert(short.to_string(), full.to_string());
+
+    info!("Aliasing {} to {}", short, full);
+    set_aliases(&args.db, &aliases).await?;
+    args.msg.react(&args.cx, '✅').await?;
+
+    Ok(())
+}
+
+/// Remove an alias set with `?alias`.  
+pub async fn remove_alias(args: Arc<Args>) -> Result<(), Error> {
+    let short = args
+        .params
+        .get("short")
+        .ok_or("Unable to retrieve param: short")?;
+    let short = short.trim_start_matches(PREFIX);
+
+    let mut aliases = aliases(&args.db).await?;
+    if aliases.remove(short).is_none() {
+        api::send_reply(args.clone(), &format!("`{}` is not an alias", short)).await?;
+        return Ok(());
+    }
+
+    info!("Removing alias {}", short);
+    set_aliases(&args.db, &aliases).await?;
+    args.msg.react(&args.cx, '✅').await?;
+
+    Ok(())
+}
+
+/// List all of the settings.  
+pub async fn get_all(args: Arc<Args>) -> Result<(), Error> {
+    let results: Vec<(String, String)> =
+        sqlx::query_as("select key, value from settings order by key")
+            .fetch_all(&*args.db)
+            .await?;
+
+    if results.is_empty() {
+        api::send_reply(args.clone(), "No settings found").await?;
+    } else {
+        let settings = results.iter().fold(String::new(), |prev, (key, value)| {
+            if prev.len() < 1900 {
+                prev + &format!("{} = {}\n", key, value)
+            } else {
+                prev
+            }
+        });
+
+        let settings = api::escape_code_block(&settings);
+        api::send_reply(args.clone(), &format!("Settings: ```\n{}```", settings)).await?;
+    }
+
+    Ok(())
+}
+
+/// Print the help message
+pub async fn help(args: Arc<Args>) -> Result<(), Error> {
+    let help_string = "Showing and changing settings is limited to administrators.
+```
+?config get {key}               Show the value of a setting.
+?config set {key} value...      Change the value of a setting.
+?config                         Show all of the settings.
+?config role {name} {role}      Use a different mod, talk or wg_and_teams role in
+                                this server.  Limited to administrators.
+?reloadconfig                   Apply the mod_id, talk_id and wg_and_teams_id
+                                settings to the roles used in every server.
+                                Limited to administrators.
+?disable {command}              Make a command, like play, reply that it is
+                                temporarily disabled.  Limited to administrators.
+?enable {command}               Let a disabled command run again.
+                                Limited to administrators.
+?alias {short} {full}           Make ?short run ?full, like `?alias p play`.
+                                Limited to administrators.
+?alias remove {short}           Remove an alias.  Limited to administrators.
+```";
+    api::send_reply(args.clone(), help_string).await?;
+    Ok(())
+}
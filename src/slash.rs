@@ -0,0 +1,141 @@
+use crate::{crates, playground, Error};
+use reqwest::Client as HttpClient;
+use serenity::{
+    model::application::{
+        command::{Command, CommandOptionType},
+        interaction::application_command::ApplicationCommandInteraction,
+    },
+    prelude::*,
+};
+use tracing::{info, info_span, Instrument};
+
+/// Register the slash commands globally for the bot.  
+pub async fn register(cx: &Context) -> Result<(), Error> {
+    info!("Registering slash commands");
+
+    Command::set_global_application_commands(cx, |commands| {
+        commands
+            .create_application_command(|c| {
+                c.name("crate")
+                    .description("Lookup crates on crates.io")
+                    .create_option(|o| {
+                        o.name("query")
+                            .description("The crate to search for")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("docs")
+                    .description("Lookup documentation")
+                    .create_option(|o| {
+                        o.name("query")
+                            .description("The crate or item path to search for")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+            })
+            .create_application_command(|c| {
+                c.name("play")
+                    .description("Compile and run rust code in a playground")
+                    .create_option(|o| {
+                        o.name("code")
+                            .description("The code to run")
+                            .kind(CommandOptionType::String)
+                            .required(true)
+                    })
+                    .create_option(|o| {
+                        o.name("mode")
+                            .description("debug or release (default: debug)")
+                            .kind(CommandOptionType::String)
+                    })
+                    .create_option(|o| {
+                        o.name("channel")
+                            .description("stable, beta or nightly (default: nightly)")
+                            .kind(CommandOptionType::String)
+                    })
+                    .create_option(|o| {
+                        o.name("edition")
+                            .description("2015, 2018 or 2021 (default: 2021)")
+                            .kind(CommandOptionType::String)
+                    })
+                    .create_option(|o| {
+                        o.name("crate")
+                            .description("bin or lib (default: lib if there is no `fn main`)")
+                            .kind(CommandOptionType::String)
+                    })
+                    .create_option(|o| {
+                        o.name("warn")
+                            .description("true to show compilation warnings")
+                            .kind(CommandOptionType::String)
+                    })
+                    .create_option(|o| {
+                        o.name("gist")
+                            .description("true to share the code and its output as a link")
+                            .kind(CommandOptionType::String)
+                    })
+                    .create_option(|o| {
+                        o.name("verbose")
+                            .description(
+                                "true to show the exact code compiled and all of its output",
+                            )
+                            .kind(CommandOptionType::String)
+                    })
+            })
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Return the value of a string option passed to a slash command.  
+pub fn option<'a>(interaction: &'a ApplicationCommandInteraction, name: &str) -> Option<&'a str> {
+    interaction
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_ref())
+        .and_then(|value| value.as_str())
+}
+
+/// Replace the deferred response of a slash command with `message`.  
+pub async fn respond(
+    cx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    message: &str,
+) -> Result<(), Error> {
+    interaction
+        .edit_original_interaction_response(cx, |r| r.content(message))
+        .await?;
+    Ok(())
+}
+
+/// Run the handler for a slash command.  
+pub async fn execute(
+    cx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    http: &HttpClient,
+) -> Result<(), Error> {
+    let span = info_span!("command", request_id = %interaction.id);
+    dispatch(cx, interaction, http).instrument(span).await
+}
+
+async fn dispatch(
+    cx: &Context,
+    interaction: &ApplicationCommandInteraction,
+    http: &HttpClient,
+) -> Result<(), Error> {
+    info!("Executing slash command {}", &interaction.data.name);
+
+    // Looking up crates and running code can take longer than the time discord allows
+    // for an initial response.
+    interaction.defer(cx).await?;
+
+    match interaction.data.name.as_str() {
+        "crate" => crates::slash_search(cx, interaction, http).await,
+        "docs" => crates::slash_doc_search(cx, interaction, http).await,
+        "play" => playground::slash_run(cx, interaction, http).await,
+        _ => respond(cx, interaction, "Unknown command").await,
+    }
+}
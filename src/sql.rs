@@ -0,0 +1,427 @@
+use crate::{api, commands::Args, Error};
+use sqlx::{
+    postgres::PgRow,
+    types::chrono::{DateTime, NaiveDateTime, Utc},
+    Column, Row, TypeInfo,
+};
+use std::{iter::Peekable, str::Chars, sync::Arc};
+use tracing::info;
+
+const MAX_ROWS: usize = 20;
+const MAX_COLUMN_WIDTH: usize = 30;
+/// Room left in a 2000 character message for the table once it is put in a code block.  
+const MAX_TABLE_LEN: usize = 1950;
+
+/// Keywords that can be followed by a parenthesis without calling a function.  
+const PAREN_KEYWORDS: [&str; 24] = [
+    "select", "from", "where", "join", "lateral", "on", "using", "and", "or", "not", "in",
+    "exists", "any", "all", "some", "values", "over", "filter", "when", "then", "else", "by",
+    "distinct", "between",
+];
+
+/// Functions a query can call, none of which has side effects or reads outside of the
+/// database's tables.  
+const ALLOWED_FUNCTIONS: [&str; 48] = [
+    "count",
+    "sum",
+    "min",
+    "max",
+    "avg",
+    "array_agg",
+    "string_agg",
+    "bool_and",
+    "bool_or",
+    "row_number",
+    "rank",
+    "dense_rank",
+    "lag",
+    "lead",
+    "coalesce",
+    "nullif",
+    "greatest",
+    "least",
+    "cast",
+    "extract",
+    "date_part",
+    "date_trunc",
+    "age",
+    "now",
+    "to_char",
+    "to_timestamp",
+    "lower",
+    "upper",
+    "length",
+    "char_length",
+    "substring",
+    "substr",
+    "position",
+    "trim",
+    "replace",
+    "concat",
+    "left",
+    "right",
+    "split_part",
+    "abs",
+    "round",
+    "floor",
+    "ceil",
+    "array_length",
+    "unnest",
+    "varchar",
+    "numeric",
+    "interval",
+];
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    /// A keyword or an unquoted name, in lowercase.  
+    Word(String),
+    /// A name in double quotes.  
+    QuotedName,
+    /// A string or number.  
+    Literal,
+    Punct(char),
+}
+
+/// Skip to the end of a string or quoted name, returning whether it was terminated.  
+///
+/// A doubled quote stands for the quote itself, and backslashes only escape the next
+/// character in `E'...'` strings.  
+fn skip_quoted(chars: &mut Peekable<Chars>, quote: char, escapes: bool) -> bool {
+    while let Some(ch) = chars.next() {
+        if escapes && ch == '\\' {
+            chars.next();
+        } else if ch == quote && chars.next_if_eq(&quote).is_none() {
+            return true;
+        }
+    }
+    false
+}
+
+/// Split a query into tokens the way postgres does, so words inside of strings and quoted
+/// names aren't mistaken for keywords.  
+fn tokens(query: &str) -> Result<Vec<Token>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            ch if ch.is_whitespace() => {}
+            '\'' => {
+                if !skip_quoted(&mut chars, '\'', false) {
+                    return Err("The query has an unterminated string.");
+                }
+                tokens.push(Token::Literal);
+            }
+            '"' => {
+                if !skip_quoted(&mut chars, '"', false) {
+                    return Err("The query has an unterminated quoted name.");
+                }
+                tokens.push(Token::QuotedName);
+            }
+            '$' => return Err("Dollar quoting and parameters can't be used in a query."),
+            '-' if chars.peek() == Some(&'-') => return Err("Comments can't be used in a query."),
+            '/' if chars.peek() == Some(&'*') => return Err("Comments can't be used in a query."),
+            ch if ch.is_ascii_digit() => {
+                while chars.next_if(|ch| ch.is_ascii_alphanumeric()).is_some() {}
+                tokens.push(Token::Literal);
+            }
+            ch if ch.is_alphabetic() || ch == '_' => {
+                let mut word = ch.to_lowercase().to_string();
+                while let Some(ch) = chars.next_if(|ch| ch.is_alphanumeric() || *ch == '_') {
+                    word.extend(ch.to_lowercase());
+                }
+
+                if word == "e" && chars.next_if_eq(&'\'').is_some() {
+                    if !skip_quoted(&mut chars, '\'', true) {
+                        return Err("The query has an unterminated string.");
+                    }
+                    tokens.push(Token::Literal);
+                } else {
+                    tokens.push(Token::Word(word));
+                }
+            }
+            ch => tokens.push(Token::Punct(ch)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Check that `query` is a single `select` statement, returning it without a trailing `;`.  
+///
+/// Every function it calls has to be in `ALLOWED_FUNCTIONS`, since a read-only transaction
+/// still allows functions with side effects, like taking an advisory lock that outlives it.  
+fn read_only_query(query: &str) -> Result<&str, &'static str> {
+    let query = query.trim().trim_end_matches(';').trim_end();
+    let tokens = tokens(query)?;
+
+    if tokens.contains(&Token::Punct(';')) {
+        return Err("Only a single statement can be run.");
+    }
+
+    if tokens.first() != Some(&Token::Word(String::from("select"))) {
+        return Err("Only `select` statements can be run.");
+    }
+
+    if tokens.contains(&Token::Word(String::from("into"))) {
+        return Err("`select into` creates a table, so it can't be run.");
+    }
+
+    for pair in tokens.windows(2) {
+        let allowed = match &pair {
+            [Token::Word(word), Token::Punct('(')] => {
+                PAREN_KEYWORDS.contains(&&word[..]) || ALLOWED_FUNCTIONS.contains(&&word[..])
+            }
+            [Token::QuotedName, Token::Punct('(')] => false,
+            _ => true,
+        };
+        if !allowed {
+            return Err("The query calls a function that isn't allowed in a read-only query.");
+        }
+    }
+
+    Ok(query)
+}
+
+/// Show one value of a row as text, for the types used in the bot's tables.  
+fn column_text(row: &PgRow, index: usize) -> String {
+    let type_name = row.columns()[index].type_info().name().to_string();
+
+    let value = match &type_name[..] {
+        "TEXT" | "VARCHAR" | "NAME" => row.try_get::<Option<String>, _>(index),
+        "INT2" => row
+            .try_get::<Option<i16>, _>(index)
+            .map(|v| v.map(|v| v.to_string())),
+        "INT4" => row
+            .try_get::<Option<i32>, _>(index)
+            .map(|v| v.map(|v| v.to_string())),
+        "INT8" => row
+            .try_get::<Option<i64>, _>(index)
+            .map(|v| v.map(|v| v.to_string())),
+        "FLOAT4" => row
+            .try_get::<Option<f32>, _>(index)
+            .map(|v| v.map(|v| v.to_string())),
+        "FLOAT8" => row
+            .try_get::<Option<f64>, _>(index)
+            .map(|v| v.map(|v| v.to_string())),
+        "BOOL" => row
+            .try_get::<Option<bool>, _>(index)
+            .map(|v| v.map(|v| v.to_string())),
+        "TIMESTAMP" => row
+            .try_get::<Option<NaiveDateTime>, _>(index)
+            .map(|v| v.map(|v| v.format("%Y-%m-%d %H:%M:%S").to_string())),
+        "TIMESTAMPTZ" => row
+            .try_get::<Option<DateTime<Utc>>, _>(index)
+            .map(|v| v.map(|v| v.format("%Y-%m-%d %H:%M:%S").to_string())),
+        _ => return format!("<{}>", type_name.to_lowercase()),
+    };
+
+    match value {
+        Ok(Some(value)) => value,
+        Ok(None) => String::from("null"),
+        Err(_) => format!("<{}>", type_name.to_lowercase()),
+    }
+}
+
+/// Lay out the rows as a table with a header, cutting off long values.  
+fn format_table(rows: &[PgRow]) -> String {
+    let names = rows[0]
+        .columns()
+        .iter()
+        .map(|column| column.name().to_string())
+        .collect::<Vec<_>>();
+
+    let cells = rows
+        .iter()
+        .take(MAX_ROWS)
+        .map(|row| {
+            (0..names.len())
+                .map(|i| {
+                    let text = column_text(row, i).replace('\n', " ");
+                    match text.char_indices().nth(MAX_COLUMN_WIDTH) {
+                        Some((end, _)) => format!("{}…", &text[..end]),
+                        None => text,
+                    }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let widths = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .chain(Some(name.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+
+    let line = |row: &[String]| {
+        row.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<1$}", cell, width))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+            + "\n"
+    };
+
+    let mut table = line(&names);
+    table += &widths
+        .iter()
+        .map(|width| "-".repeat(*width))
+        .collect::<Vec<_>>()
+        .join("-+-");
+    table += "\n";
+
+    let mut shown = 0;
+    for row in &cells {
+        let line = line(row);
+        if table.len() + line.len() > MAX_TABLE_LEN {
+            break;
+        }
+        table += &line;
+        shown += 1;
+    }
+
+    if shown < cells.len() {
+        table += &format!("({} of {} rows shown)\n", shown, cells.len());
+    }
+
+    table
+}
+
+/// Run a read-only `select` against the database and show the rows as a table.  
+///
+/// Besides checking the statement, it runs in a read-only transaction that is always rolled
+/// back, so nothing can be changed even if a check is missed.  
+pub async fn sql(args: Arc<Args>) -> Result<(), Error> {
+    let query = args
+        .params
+        .get("query")
+        .ok_or("Unable to retrieve param: query")?;
+
+    let query = match read_only_query(query) {
+        Ok(query) => query,
+        Err(e) => {
+            api::send_reply(args.clone(), e).await?;
+            return Ok(());
+        }
+    };
+
+    info!("Running sql query for {}: {}", args.msg.author.id, query);
+    let mut transaction = args.db.begin().await?;
+    sqlx::query("set transaction read only")
+        .execute(&mut transaction)
+        .await?;
+    sqlx::query("set local statement_timeout = '5s'")
+        .execute(&mut transaction)
+        .await?;
+
+    // `limit` is applied outside, so the statement itself can't ask for every row
+    let limited = format!("select * from ({}) as query limit {}", query, MAX_ROWS + 1);
+    let rows = sqlx::query(&limited).fetch_all(&mut transaction).await;
+    transaction.rollback().await?;
+
+    let message = match rows {
+        Ok(rows) if rows.is_empty() => String::from("No rows returned."),
+        Ok(rows) => {
+            let mut table = format_table(&rows);
+            if rows.len() > MAX_ROWS {
+                table += &format!("(only the first {} rows are fetched)\n", MAX_ROWS);
+            }
+            format!("```\n{}```", api::escape_code_block(&table))
+        }
+        Err(e) => format!("Query failed: {}", e),
+    };
+
+    api::send_reply(args.clone(), &message).await?;
+    Ok(())
+}
+
+/// Print the help message
+pub async fn help(args: Arc<Args>) -> Result<(), Error> {
+    let help_string = "
+Run a read-only query against the bot's database and show the results as a table
+```
+?sql query...
+```
+**Example:**
+```
+?sql select * from bans where unbanned = false
+```
+Only a single `select` statement is allowed, and at most 20 rows are shown.
+Only common functions like `count`, `lower` or `date_trunc` can be called.";
+    api::send_reply(args.clone(), help_string).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_are_allowed() {
+        let allowed = [
+            "select * from bans where unbanned = false",
+            "select count(*) from tags;",
+            "select lower(key), length(value) from tags where key in ('a', 'b')",
+            "select 'a;b', 'set do into lock' as words",
+            "select \"set\", \"do\" from settings",
+            "select e'it\\'s' as text",
+            "select * from tags where exists (select 1 from users)",
+        ];
+        for query in allowed {
+            assert!(read_only_query(query).is_ok(), "{}", query);
+        }
+
+        assert_eq!(read_only_query(" select 1; "), Ok("select 1"));
+    }
+
+    #[test]
+    fn writes_are_rejected() {
+        let rejected = [
+            "update tags set value = ''",
+            "delete from bans",
+            "select 1; drop table tags",
+            "select * into copy from tags",
+            "with t as (delete from bans returning *) select * from t",
+        ];
+        for query in rejected {
+            assert!(read_only_query(query).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn functions_have_to_be_allowed() {
+        let rejected = [
+            "select pg_advisory_lock(1)",
+            "select pg_advisory_xact_lock(1)",
+            "select pg_catalog.pg_sleep_for('1 minute')",
+            "select * from dblink('host=example.com', 'select 1') as t(a int)",
+            "select pg_stat_file('postgresql.conf')",
+            "select \"pg_sleep\"(1)",
+            "select PG_SLEEP (1)",
+        ];
+        for query in rejected {
+            assert!(read_only_query(query).is_err(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn strings_end_where_postgres_ends_them() {
+        // Backslashes don't escape quotes in a standard string, so the call is outside of it
+        assert!(read_only_query("select 'a\\' , pg_sleep(1), '\\'").is_err());
+        assert!(read_only_query("select 'it''s', pg_sleep(1)").is_err());
+
+        assert!(read_only_query("select 'unterminated").is_err());
+        assert!(read_only_query("select $$pg_sleep(1)$$").is_err());
+        assert!(read_only_query("select 1 -- ;").is_err());
+    }
+}
@@ -124,9 +124,10 @@ impl State {
 #[derive(Debug, Clone)]
 pub struct Traversal {
     current_state: usize,
-    positions: Vec<(usize, usize, Option<&'static str>)>,
+    positions: Vec<(usize, usize, Option<&'static str>, bool)>,
     segment_start: Option<usize>,
     segment_name: Option<&'static str>,
+    segment_escaped: bool,
 }
 
 impl Traversal {
@@ -137,31 +138,59 @@ impl Traversal {
             positions: Vec::new(),
             segment_start: None,
             segment_name: None,
+            segment_escaped: false,
         }
     }
 
     /// Mark the position in the input where a dynamic segment begins.  
-    pub fn set_segment_start(&mut self, pos: usize, name: &'static str) {
+    pub fn set_segment_start(&mut self, pos: usize, name: &'static str, escaped: bool) {
         self.segment_start = Some(pos);
         self.segment_name = Some(name);
+        self.segment_escaped = escaped;
     }
 
     /// Mark the position in the input where a dynamic segment ends.   
     pub fn set_segment_end(&mut self, pos: usize) {
-        self.positions
-            .push((self.segment_start.unwrap(), pos, self.segment_name.take()));
+        self.positions.push((
+            self.segment_start.unwrap(),
+            pos,
+            self.segment_name.take(),
+            self.segment_escaped,
+        ));
         self.segment_start = None;
     }
 
     /// Returns a `HashMap` containing the dynamic segments parsed from the input.  
     pub fn extract<'a>(&self, input: &'a str) -> HashMap<&'static str, String> {
-        self.positions
-            .iter()
-            .fold(HashMap::new(), |mut hash_map, (start, end, name)| {
-                hash_map.insert(name.unwrap(), input[*start..*end].to_string());
+        self.positions.iter().fold(
+            HashMap::new(),
+            |mut hash_map, (start, end, name, escaped)| {
+                let value = &input[*start..*end];
+                let value = if *escaped {
+                    unescape(value)
+                } else {
+                    value.to_string()
+                };
+                hash_map.insert(name.unwrap(), value);
                 hash_map
-            })
+            },
+        )
+    }
+}
+
+/// Replace `\"` with `"` and `\\` with `\`.  
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => unescaped.extend(chars.next()),
+            _ => unescaped.push(ch),
+        }
     }
+
+    unescaped
 }
 
 pub struct Match {
@@ -173,6 +202,7 @@ pub struct StateMachine {
     states: Vec<State>,
     start_parse: Vec<Option<&'static str>>,
     end_parse: Vec<bool>,
+    unescape: Vec<bool>,
 }
 
 impl StateMachine {
@@ -181,6 +211,7 @@ impl StateMachine {
             states: vec![State::new(0, CharacterSet::new())],
             start_parse: vec![None],
             end_parse: vec![false],
+            unescape: vec![false],
         }
     }
 
@@ -214,6 +245,7 @@ impl StateMachine {
         self.states.push(state);
         self.start_parse.push(None);
         self.end_parse.push(false);
+        self.unescape.push(false);
         index
     }
 
@@ -234,7 +266,17 @@ impl StateMachine {
         self.end_parse[index] = true;
     }
 
-    /// Run the input through the state machine, optionally returning a handler and params.  
+    /// Mark that the dynamic segment starting at the index in the state machine may contain
+    /// backslash escapes, which are removed from the parsed value.  
+    pub fn unescape(&mut self, index: usize) {
+        self.unescape[index] = true;
+    }
+
+    /// Run the input through the state machine, returning the final state it reached and the
+    /// dynamic segments parsed along the way, or `None` if the input doesn't match.  
+    ///
+    /// Every possible path is followed at once; when more than one ends in a final state, the
+    /// path through the states added first is used.  
     pub fn process<'m>(&'m self, input: &'m str) -> Option<Match> {
         let mut traversals = vec![Traversal::new()];
 
@@ -321,13 +363,165 @@ impl StateMachine {
         let start_parse = &self.start_parse[next_state];
 
         if traversal.segment_start.is_none() && start_parse.is_some() {
-            traversal.set_segment_start(pos, start_parse.unwrap());
+            traversal.set_segment_start(pos, start_parse.unwrap(), self.unescape[next_state]);
         }
+        // A segment can span several states, like an escape sequence inside a quoted value
         if traversal.segment_start.is_some()
             && self.end_parse[current_state]
-            && current_state != next_state
+            && self.start_parse[current_state] != *start_parse
         {
             traversal.set_segment_end(pos);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{Args, Command, Commands},
+        Error,
+    };
+    use std::sync::Arc;
+
+    async fn noop(_: Arc<Args>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// `?hi {name}`, built by hand to run `process` without `Commands`.  
+    fn greeting() -> (StateMachine, usize) {
+        let mut sm = StateMachine::new();
+        let mut state = 0;
+        for ch in "?hi ".chars() {
+            state = sm.add(state, CharacterSet::from_char(ch));
+        }
+
+        let mut name = CharacterSet::any();
+        name.remove(&[' ']);
+        state = sm.add(state, name);
+        sm.add_next_state(state, state);
+        sm.start_parse(state, "name");
+        sm.end_parse(state);
+        sm.set_final_state(state);
+
+        (sm, state)
+    }
+
+    fn commands() -> Commands {
+        let mut cmds = Commands::new();
+        cmds.add("?ban {user} {duration} reason...", Command::new(&noop));
+        cmds.add(
+            "?play mode={} edition={} warn={} ```\ncode``` ...",
+            Command::new(&noop),
+        );
+        cmds.add("?play mode={} edition={} warn={}", Command::new(&noop));
+        cmds.add("?tags create {key} value...", Command::new(&noop));
+        cmds.add("?tags", Command::new(&noop));
+        cmds
+    }
+
+    fn params(input: &str) -> HashMap<&'static str, String> {
+        let (_, params) = commands().find(input).expect("no command matched");
+        params
+    }
+
+    #[test]
+    fn process_parses_a_dynamic_segment() {
+        let (sm, end) = greeting();
+
+        let matched = sm.process("?hi ferris").unwrap();
+        assert_eq!(matched.state, end);
+        assert_eq!(matched.params["name"], "ferris");
+
+        assert!(sm.process("?hi").is_none());
+        assert!(sm.process("?hello ferris").is_none());
+        assert!(sm.process("?hi ferris crab").is_none());
+    }
+
+    #[test]
+    fn find_matches_the_registered_pattern() {
+        let cmds = commands();
+
+        let (command, _) = cmds.find("?tags").unwrap();
+        assert_eq!(command.pattern, "?tags");
+
+        let (command, _) = cmds.find("?tags create hello hi there").unwrap();
+        assert_eq!(command.pattern, "?tags create {key} value...");
+
+        assert!(cmds.find("?tagz").is_none());
+    }
+
+    #[test]
+    fn key_value_params_in_any_order() {
+        let input = "?play mode=release edition=2018 warn=true ```\nfn main() {}```";
+        let params = params(input);
+        assert_eq!(params["mode"], "release");
+        assert_eq!(params["edition"], "2018");
+        assert_eq!(params["warn"], "true");
+
+        let params = self::params("?play warn=true mode=release ```\nfn main() {}```");
+        assert_eq!(params["mode"], "release");
+        assert_eq!(params["warn"], "true");
+        assert!(!params.contains_key("edition"));
+
+        let params = self::params("?play edition=2015 mode=debug");
+        assert_eq!(params["edition"], "2015");
+        assert_eq!(params["mode"], "debug");
+    }
+
+    #[test]
+    fn quoted_values_keep_their_spaces() {
+        let params = params("?play warn=\"a b c\" mode=release");
+        assert_eq!(params["warn"], "a b c");
+        assert_eq!(params["mode"], "release");
+    }
+
+    #[test]
+    fn quoted_values_capture_escaped_quotes() {
+        let params = params(r#"?play warn="a \"b\" c""#);
+        assert_eq!(params["warn"], r#"a "b" c"#);
+    }
+
+    #[test]
+    fn quoted_values_end_in_an_escaped_backslash() {
+        let params = params(r#"?play warn="a\\" mode=release"#);
+        assert_eq!(params["warn"], r"a\");
+        assert_eq!(params["mode"], "release");
+
+        // The backslash escapes the closing quote, so the value never ends
+        assert!(commands().find(r#"?play warn="a\""#).is_none());
+    }
+
+    #[test]
+    fn adjacent_segments_are_parsed_separately() {
+        let params = params("?ban @ferris 1d spamming links");
+        assert_eq!(params["user"], "@ferris");
+        assert_eq!(params["duration"], "1d");
+        assert_eq!(params["reason"], "spamming links");
+
+        let params = self::params(r#"?play warn="x" mode="y""#);
+        assert_eq!(params["warn"], "x");
+        assert_eq!(params["mode"], "y");
+    }
+
+    #[test]
+    fn multi_line_code_blocks() {
+        let params = params("?play ```rust\nfn main() {\n    println!(\"hi\");\n}\n```");
+        assert_eq!(params["code"], "fn main() {\n    println!(\"hi\");\n}\n");
+
+        let params = self::params("?play mode=release\n```\nlet a = 1;\nlet b = 2;```");
+        assert_eq!(params["mode"], "release");
+        assert_eq!(params["code"], "let a = 1;\nlet b = 2;");
+    }
+
+    #[test]
+    fn remaining_segments() {
+        let params = params("?tags create hello hi there,\nfriend");
+        assert_eq!(params["key"], "hello");
+        assert_eq!(params["value"], "hi there,\nfriend");
+
+        // An unnamed `...` accepts anything after the code block without parsing it
+        let params = self::params("?play ```\nfn main() {}``` run this please");
+        assert_eq!(params["code"], "fn main() {}");
+    }
+}
@@ -1,6 +1,70 @@
 use crate::{api, commands::Args, Error};
 
-use std::sync::Arc;
+use serenity::model::prelude::*;
+use sqlx::{
+    types::chrono::{DateTime, Utc},
+    Postgres, Transaction,
+};
+use std::{sync::Arc, time::SystemTime};
+
+/// Number of edits kept in the history of each tag.  
+const MAX_HISTORY: i64 = 10;
+
+/// Old value, new value, editor id and time of an edit.  
+type HistoryRow = (Option<String>, Option<String>, String, DateTime<Utc>);
+
+/// Record a change to a tag, pruning the oldest entries of its history.  
+async fn record_history(
+    transaction: &mut Transaction<'_, Postgres>,
+    key: &str,
+    old_value: Option<&str>,
+    new_value: Option<&str>,
+    editor_id: UserId,
+) -> Result<(), Error> {
+    sqlx::query(
+        "insert into tag_history(key, old_value, new_value, editor_id, edited_at)
+            values ($1, $2, $3, $4, $5)",
+    )
+    .bind(key)
+    .bind(old_value)
+    .bind(new_value)
+    .bind(editor_id.to_string())
+    .bind(DateTime::<Utc>::from(SystemTime::now()))
+    .execute(&mut *transaction)
+    .await?;
+
+    sqlx::query(
+        "delete from tag_history where key = $1 and id not in
+            (select id from tag_history where key = $1 order by id desc limit $2)",
+    )
+    .bind(key)
+    .bind(MAX_HISTORY)
+    .execute(&mut *transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Check whether a tag is locked, locking its row until the end of the transaction.  
+async fn is_locked(transaction: &mut Transaction<'_, Postgres>, key: &str) -> Result<bool, Error> {
+    let row: Option<(bool,)> = sqlx::query_as("select locked from tags where key = $1 for update")
+        .bind(key)
+        .fetch_optional(&mut *transaction)
+        .await?;
+
+    Ok(row.is_some_and(|(locked,)| locked))
+}
+
+async fn reply_locked(args: Arc<Args>, key: &str) -> Result<(), Error> {
+    api::send_reply(
+        args.clone(),
+        &format!(
+            "`{}` is locked, an administrator needs to unlock it first.",
+            key
+        ),
+    )
+    .await
+}
 
 /// Remove a key value pair from the tags.  
 pub async fn delete(args: Arc<Args>) -> Result<(), Error> {
@@ -9,20 +73,40 @@ pub async fn delete(args: Arc<Args>) -> Result<(), Error> {
         .get("key")
         .ok_or("Unable to retrieve param: key")?;
 
-    let query = sqlx::query("delete from tags where key = $1")
-        .bind(key)
-        .execute(&*args.clone().db)
+    let mut transaction = args.db.begin().await?;
+
+    if is_locked(&mut transaction, key).await? {
+        return reply_locked(args.clone(), key).await;
+    }
+
+    let old_value: Option<(String,)> =
+        sqlx::query_as("delete from tags where key = $1 returning value")
+            .bind(key)
+            .fetch_optional(&mut transaction)
+            .await?;
+
+    if let Some((old_value,)) = &old_value {
+        record_history(
+            &mut transaction,
+            key,
+            Some(old_value),
+            None,
+            args.msg.author.id,
+        )
         .await?;
+    }
 
-    match query.rows_affected() {
-        0 => {
+    transaction.commit().await?;
+
+    match old_value {
+        None => {
             api::send_reply(
                 args.clone(),
                 "A database error occurred when deleting the tag.",
             )
             .await?;
         }
-        _ => {
+        Some(_) => {
             args.msg.react(&args.cx, '✅').await?;
         }
     }
@@ -42,12 +126,20 @@ pub async fn post(args: Arc<Args>) -> Result<(), Error> {
         .get("value")
         .ok_or("Unable to retrieve param: value")?;
 
+    let mut transaction = args.db.begin().await?;
+
     let query = sqlx::query("insert into tags(key, value) values ($1, $2)")
         .bind(key)
         .bind(value)
-        .execute(&*args.clone().db)
+        .execute(&mut transaction)
         .await?;
 
+    if query.rows_affected() > 0 {
+        record_history(&mut transaction, key, None, Some(value), args.msg.author.id).await?;
+    }
+
+    transaction.commit().await?;
+
     match query.rows_affected() {
         0 => {
             api::send_reply(
@@ -75,12 +167,37 @@ pub async fn update(args: Arc<Args>) -> Result<(), Error> {
         .get("value")
         .ok_or("Unable to retrieve param: value")?;
 
+    let mut transaction = args.db.begin().await?;
+
+    if is_locked(&mut transaction, key).await? {
+        return reply_locked(args.clone(), key).await;
+    }
+
+    let old_value: Option<(String,)> =
+        sqlx::query_as("select value from tags where key = $1 for update")
+            .bind(key)
+            .fetch_optional(&mut transaction)
+            .await?;
+
     let query = sqlx::query("update tags set value = $1 where key = $2")
         .bind(value)
         .bind(key)
-        .execute(&*args.clone().db)
+        .execute(&mut transaction)
         .await?;
 
+    if let Some((old_value,)) = &old_value {
+        record_history(
+            &mut transaction,
+            key,
+            Some(old_value),
+            Some(value),
+            args.msg.author.id,
+        )
+        .await?;
+    }
+
+    transaction.commit().await?;
+
     match query.rows_affected() {
         0 => {
             api::send_reply(
@@ -97,12 +214,46 @@ pub async fn update(args: Arc<Args>) -> Result<(), Error> {
     Ok(())
 }
 
+async fn set_locked(args: Arc<Args>, locked: bool) -> Result<(), Error> {
+    let key = args
+        .params
+        .get("key")
+        .ok_or("Unable to retrieve param: key")?;
+
+    let query = sqlx::query("update tags set locked = $1 where key = $2")
+        .bind(locked)
+        .bind(key)
+        .execute(&*args.db)
+        .await?;
+
+    match query.rows_affected() {
+        0 => {
+            api::send_reply(args.clone(), &format!("Tag not found for `{}`", key)).await?;
+        }
+        _ => {
+            args.msg.react(&args.cx, '✅').await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Protect a tag from being updated or deleted.  
+pub async fn lock(args: Arc<Args>) -> Result<(), Error> {
+    set_locked(args, true).await
+}
+
+/// Allow a locked tag to be updated or deleted again.  
+pub async fn unlock(args: Arc<Args>) -> Result<(), Error> {
+    set_locked(args, false).await
+}
+
 /// Retrieve a value by key from the tags.
 pub async fn get(args: Arc<Args>) -> Result<(), Error> {
     let key = args.params.get("key").ok_or("unable to read params")?;
 
     let results: Option<(i32, String, String)> =
-        sqlx::query_as("select * from tags where key = $1 limit 1")
+        sqlx::query_as("select id, key, value from tags where key = $1 limit 1")
             .bind(key)
             .fetch_optional(&*args.db)
             .await?;
@@ -116,9 +267,35 @@ pub async fn get(args: Arc<Args>) -> Result<(), Error> {
     Ok(())
 }
 
+/// Retrieve the value of a random tag.  
+pub async fn random(args: Arc<Args>) -> Result<(), Error> {
+    let result: Option<(String,)> =
+        sqlx::query_as("select value from tags order by random() limit 1")
+            .fetch_optional(&*args.db)
+            .await?;
+
+    if let Some((value,)) = result {
+        api::send_reply(args.clone(), &value).await?;
+    } else {
+        api::send_reply(args.clone(), "No tags found").await?;
+    }
+
+    Ok(())
+}
+
+/// Reply with the number of tags.  
+pub async fn count(args: Arc<Args>) -> Result<(), Error> {
+    let (count,): (i64,) = sqlx::query_as("select count(*) from tags")
+        .fetch_one(&*args.db)
+        .await?;
+
+    api::send_reply(args.clone(), &format!("There are {} tags", count)).await?;
+    Ok(())
+}
+
 /// Retrieve all tags
 pub async fn get_all(args: Arc<Args>) -> Result<(), Error> {
-    let results: Vec<(i32, String, String)> = sqlx::query_as("select * from tags")
+    let results: Vec<(i32, String, String)> = sqlx::query_as("select id, key, value from tags")
         .fetch_all(&*args.db)
         .await?;
 
@@ -133,18 +310,85 @@ pub async fn get_all(args: Arc<Args>) -> Result<(), Error> {
             }
         });
 
+        let tags = api::escape_code_block(tags);
         api::send_reply(args.clone(), &format!("All tags: ```\n{}```", &tags)).await?;
     }
 
     Ok(())
 }
 
+/// Show the most recent edits of a tag.  
+pub async fn history(args: Arc<Args>) -> Result<(), Error> {
+    let key = args
+        .params
+        .get("key")
+        .ok_or("Unable to retrieve param: key")?;
+
+    let results: Vec<HistoryRow> = sqlx::query_as(
+        "select old_value, new_value, editor_id, edited_at from tag_history
+            where key = $1 order by id desc",
+    )
+    .bind(key)
+    .fetch_all(&*args.db)
+    .await?;
+
+    if results.is_empty() {
+        api::send_reply(args.clone(), &format!("No history found for `{}`", key)).await?;
+        return Ok(());
+    }
+
+    use std::str::FromStr;
+
+    let history = results
+        .iter()
+        .map(|(old_value, new_value, editor_id, edited_at)| {
+            let action = match (old_value, new_value) {
+                (None, _) => "created",
+                (_, None) => "deleted",
+                _ => "updated",
+            };
+
+            let editor = u64::from_str(editor_id)
+                .ok()
+                .and_then(|id| args.cx.cache.user(id))
+                .map(|user| user.tag())
+                .unwrap_or_else(|| editor_id.clone());
+
+            format!(
+                "{} {:<8} by {}\n",
+                edited_at.format("%Y-%m-%d %H:%M"),
+                action,
+                editor
+            )
+        })
+        .collect::<String>();
+
+    api::send_reply(
+        args.clone(),
+        &format!(
+            "History of `{}` (UTC): ```\n{}```",
+            key,
+            api::escape_code_block(&history)
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
 /// Print the help message
 pub async fn help(args: Arc<Args>) -> Result<(), Error> {
     let help_string = "```
 ?tags create {key} value...     Create a tag.  Limited to WG & Teams.
 ?tags update {key} value...     Update a tag.  Limited to WG & Teams.
 ?tags delete {key}              Delete a tag.  Limited to WG & Teams.
+?tags history {key}             Show the recent edits of a tag.
+?tags lock {key}                Stop a tag from being updated or deleted.
+                                Limited to administrators.
+?tags unlock {key}              Allow a locked tag to be changed again.
+                                Limited to administrators.
+?tags random                    Get a random tag.
+?tags count                     Show how many tags there are.
 ?tags help                      This menu.
 ?tags                           Get all the tags.
 ?tag {key}                      Get a specific tag.
@@ -1,11 +1,14 @@
+use crate::duration::format_duration;
+use std::time::Duration;
+
 pub const WELCOME_BILLBOARD: &str = "By participating in this community, you agree to follow the Rust Code of Conduct, as linked below. Please click the :white_check_mark: below to acknowledge and gain access to the channels.
 
   https://www.rust-lang.org/policies/code-of-conduct
 
 If you see someone behaving inappropriately, or otherwise against the Code of Conduct, please contact the mods using `@mods` or by DM'ing a mod from the sidebar.  ";
 
-pub fn ban_message(reason: &str, hours: u64) -> String {
-    format!("You have been banned from The Rust Programming Language discord server for {}. The ban will expire in {} hours. If you feel this action was taken unfairly, you can reach the Rust moderation team at discord-mods@rust-lang.org", reason, hours)
+pub fn ban_message(reason: &str, duration: Duration) -> String {
+    format!("You have been banned from The Rust Programming Language discord server for {}. The ban will expire in {}. If you feel this action was taken unfairly, you can reach the Rust moderation team at discord-mods@rust-lang.org", reason, format_duration(duration))
 }
 
 pub const WG_AND_TEAMS_MISSING_ENV_VAR: &str = "missing value for field wg_and_teams_id.\n\nIf you enabled tags or crates then you need the WG_AND_TEAMS_ID env var.";
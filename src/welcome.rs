@@ -1,36 +1,110 @@
-use crate::{api, commands::Args, text::WELCOME_BILLBOARD, Error};
+use crate::{
+    api,
+    commands::{param_error, Args},
+    settings,
+    text::WELCOME_BILLBOARD,
+    Error,
+};
 use serenity::{model::prelude::*, prelude::*};
 use sqlx::postgres::PgPool;
-use std::sync::Arc;
-use tracing::info;
+use std::{str::FromStr, sync::Arc};
+use tokio::time::{sleep, Duration};
+use tracing::{error, info};
+
+const WELCOME_TEXT_SETTING: &str = "welcome_text";
+const WELCOME_EMOJI_SETTING: &str = "welcome_emoji";
+const DEFAULT_WELCOME_EMOJI: &str = "✅";
+/// The most reactions handled per welcome message when the bot starts, one page from discord.  
+const MAX_RESUMED_REACTIONS: u8 = 100;
+/// Time left between two members handled on start, to stay clear of discord's rate limits.  
+const RESUME_DELAY: Duration = Duration::from_millis(500);
+
+/// The key a welcome setting is stored under for `guild_id`, like `welcome_text:1234`.  
+fn guild_setting_key(key: &str, guild_id: Option<GuildId>) -> String {
+    match api::guild_key(guild_id) {
+        guild_key if guild_key.is_empty() => key.to_string(),
+        guild_key => format!("{}:{}", key, guild_key),
+    }
+}
+
+/// Look up a welcome setting for `guild_id`, falling back to the one shared by every guild.  
+async fn guild_setting(
+    db: &PgPool,
+    key: &str,
+    guild_id: Option<GuildId>,
+) -> Result<Option<String>, Error> {
+    match settings::get(db, &guild_setting_key(key, guild_id)).await? {
+        Some(value) => Ok(Some(value)),
+        None => settings::get(db, key).await,
+    }
+}
+
+async fn welcome_text(db: &PgPool, guild_id: Option<GuildId>) -> Result<String, Error> {
+    Ok(guild_setting(db, WELCOME_TEXT_SETTING, guild_id)
+        .await?
+        .unwrap_or_else(|| WELCOME_BILLBOARD.to_string()))
+}
+
+/// The emoji members react with to get the talk role, either unicode or a custom `<:name:id>`.  
+async fn welcome_emoji(db: &PgPool, guild_id: Option<GuildId>) -> Result<ReactionType, Error> {
+    let emoji = guild_setting(db, WELCOME_EMOJI_SETTING, guild_id)
+        .await?
+        .unwrap_or_else(|| DEFAULT_WELCOME_EMOJI.to_string());
+
+    Ok(ReactionType::from_str(emoji.trim())?)
+}
+
+/// Compare custom emoji by id only, as reaction events don't always carry their name.  
+fn same_emoji(a: &ReactionType, b: &ReactionType) -> bool {
+    match (a, b) {
+        (ReactionType::Custom { id: a, .. }, ReactionType::Custom { id: b, .. }) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Look up the message and channel ids of the welcome message posted in `guild_id`.  
+async fn welcome_message(
+    db: &PgPool,
+    guild_id: Option<GuildId>,
+) -> Result<Option<(String, String)>, Error> {
+    Ok(sqlx::query_as(
+        "select message, channel from messages where name = 'welcome' and guild_id in ($1, '')
+            order by guild_id desc limit 1",
+    )
+    .bind(api::guild_key(guild_id))
+    .fetch_optional(db)
+    .await?)
+}
 
 /// Write the welcome message to the welcome channel.  
 pub async fn post_message(args: Arc<Args>) -> Result<(), Error> {
-    use std::str::FromStr;
-
     if api::is_mod(args.clone()).await? {
         let channel_name = &args
             .params
             .get("channel")
             .ok_or("unable to retrieve channel param")?;
 
-        let channel_id = ChannelId::from_str(channel_name)?;
+        let channel_id = ChannelId::from_str(channel_name)
+            .map_err(|_| param_error(format!("`{}` isn't a channel mention", channel_name)))?;
 
         info!("Posting welcome message");
-        let message = channel_id.say(&args.cx, WELCOME_BILLBOARD).await?;
+        let text = welcome_text(&args.db, args.msg.guild_id).await?;
+        let message = channel_id.say(&args.cx, text).await?;
 
         let message_id = message.id.0.to_string();
         let bot_id = message.author.id.to_string();
         let channel_id = channel_id.0.to_string();
+        let guild_id = api::guild_key(args.msg.guild_id);
 
         let mut transaction = args.db.begin().await?;
 
-        let save_message =
-            "insert into messages (name, message, channel) values ('welcome', $1, $2)
-            on conflict (name) do update set message = $1, channel = $2";
+        let save_message = "insert into messages (name, message, channel, guild_id)
+            values ('welcome', $1, $2, $3)
+            on conflict (name, guild_id) do update set message = $1, channel = $2";
         sqlx::query(save_message)
             .bind(message_id)
             .bind(channel_id)
+            .bind(guild_id)
             .execute(&mut transaction)
             .await?;
 
@@ -45,12 +119,56 @@ pub async fn post_message(args: Arc<Args>) -> Result<(), Error> {
 
         transaction.commit().await?;
 
-        let white_check_mark = ReactionType::from_str("✅")?;
-        message.react(&args.cx, white_check_mark).await?;
+        let emoji = welcome_emoji(&args.db, args.msg.guild_id).await?;
+        message.react(&args.cx, emoji).await?;
     }
     Ok(())
 }
 
+/// Change the text of the welcome message, editing the posted message if there is one.  
+pub async fn set_text(args: Arc<Args>) -> Result<(), Error> {
+    if api::is_mod(args.clone()).await? {
+        let text = args
+            .params
+            .get("text")
+            .ok_or("unable to retrieve text param")?;
+
+        let key = guild_setting_key(WELCOME_TEXT_SETTING, args.msg.guild_id);
+        settings::set(&args.db, &key, text).await?;
+
+        let msg = welcome_message(&args.db, args.msg.guild_id).await?;
+
+        if let Some((message_id, channel_id)) = msg {
+            info!("Editing welcome message");
+            ChannelId::from(u64::from_str(&channel_id)?)
+                .edit_message(&args.cx, u64::from_str(&message_id)?, |m| m.content(text))
+                .await?;
+        }
+
+        args.msg.react(&args.cx, '✅').await?;
+    }
+    Ok(())
+}
+
+/// Change the emoji members react with to the welcome message of this guild.  
+///
+/// Reactions to a welcome message posted before the change are only handled once the message
+/// is posted again.  
+pub async fn set_emoji(args: Arc<Args>) -> Result<(), Error> {
+    let emoji = args
+        .params
+        .get("emoji")
+        .ok_or("unable to retrieve emoji param")?;
+    ReactionType::from_str(emoji.trim())
+        .map_err(|_| param_error(format!("`{}` isn't an emoji", emoji)))?;
+
+    let key = guild_setting_key(WELCOME_EMOJI_SETTING, args.msg.guild_id);
+    settings::set(&args.db, &key, emoji.trim()).await?;
+
+    args.msg.react(&args.cx, '✅').await?;
+    Ok(())
+}
+
 pub async fn assign_talk_role(
     cx: &Context,
     reaction: &Reaction,
@@ -60,31 +178,21 @@ pub async fn assign_talk_role(
     let channel_id = ChannelId::from(&channel);
     let message = reaction.message(cx).await?;
 
-    let mut transaction = db.begin().await?;
-
-    let msg: Option<(i32, String, String, String)> =
-        sqlx::query_as("select * from messages where name = 'welcome' limit 1")
-            .fetch_optional(&mut transaction)
-            .await?;
-
-    let talk_role: Option<(i32, String, String)> =
-        sqlx::query_as("select * from roles where name = 'talk' limit 1")
-            .fetch_optional(&mut transaction)
-            .await?;
+    let msg = welcome_message(&db, reaction.guild_id).await?;
+    let talk_role = api::role_id(&db, "talk", reaction.guild_id).await?;
+    let emoji = welcome_emoji(&db, reaction.guild_id).await?;
 
     let me: Option<(i32, String, String)> =
         sqlx::query_as("select * from users where name = 'me' limit 1")
-            .fetch_optional(&mut transaction)
+            .fetch_optional(&*db)
             .await?;
 
-    transaction.commit().await?;
-
-    if let Some((_, _, cached_message_id, cached_channel_id)) = msg {
+    if let Some((cached_message_id, cached_channel_id)) = msg {
         if message.id.0.to_string() == cached_message_id
             && channel_id.0.to_string() == *cached_channel_id
         {
-            if reaction.emoji == ReactionType::from('✅') {
-                if let Some((_, role_id, _)) = talk_role {
+            if same_emoji(&reaction.emoji, &emoji) {
+                if let Some(role_id) = talk_role {
                     if let Some(user_id) = reaction.user_id {
                         let guild = channel
                             .guild()
@@ -92,7 +200,6 @@ pub async fn assign_talk_role(
 
                         let mut member = guild.guild_id.member(cx, user_id).await?;
 
-                        use std::str::FromStr;
                         info!("Assigning talk role to {}", &member.user.id);
                         member
                             .add_role(&cx, RoleId::from(u64::from_str(&role_id)?))
@@ -114,6 +221,111 @@ pub async fn assign_talk_role(
     Ok(())
 }
 
+/// Give the talk role to members who reacted to a welcome message while the bot was offline.  
+///
+/// Reactions are removed once handled, so whatever is left on the message wasn't seen.  
+pub async fn resume_reactions(cx: &Context, db: &PgPool) -> Result<(), Error> {
+    let messages: Vec<(String, String, String)> =
+        sqlx::query_as("select message, channel, guild_id from messages where name = 'welcome'")
+            .fetch_all(db)
+            .await?;
+    let bot_id = cx.cache.current_user_id();
+
+    for (message_id, channel_id, guild_key) in messages {
+        let message_id = MessageId::from(u64::from_str(&message_id)?);
+        let channel_id = ChannelId::from(u64::from_str(&channel_id)?);
+
+        // Messages posted before roles were per guild are stored without one
+        let guild_id = if guild_key.is_empty() {
+            match channel_id.to_channel(cx).await {
+                Ok(channel) => channel.guild().map(|channel| channel.guild_id),
+                Err(e) => {
+                    error!("Unable to look up the channel {}: {}", channel_id, e);
+                    continue;
+                }
+            }
+        } else {
+            Some(GuildId::from(u64::from_str(&guild_key)?))
+        };
+        let guild_id = match guild_id {
+            Some(guild_id) => guild_id,
+            None => continue,
+        };
+        let emoji = match welcome_emoji(db, Some(guild_id)).await {
+            Ok(emoji) => emoji,
+            Err(e) => {
+                error!("Unable to look up the welcome emoji of {}: {}", guild_id, e);
+                continue;
+            }
+        };
+
+        // One message that can't be read, like from a deleted channel, shouldn't hold up the rest
+        let users = match channel_id
+            .reaction_users(
+                cx,
+                message_id,
+                emoji.clone(),
+                Some(MAX_RESUMED_REACTIONS),
+                None,
+            )
+            .await
+        {
+            Ok(users) => users,
+            Err(e) => {
+                error!(
+                    "Unable to read the reactions of welcome message {}: {}",
+                    message_id, e
+                );
+                continue;
+            }
+        };
+        let users = users
+            .into_iter()
+            .filter(|user| user.id != bot_id && !user.bot)
+            .collect::<Vec<_>>();
+        if users.is_empty() {
+            continue;
+        }
+
+        let role_id = match api::role_id(db, "talk", Some(guild_id)).await? {
+            Some(role_id) => RoleId::from(u64::from_str(&role_id)?),
+            None => continue,
+        };
+
+        info!(
+            "Handling {} welcome reactions added while offline",
+            users.len()
+        );
+        for user in users {
+            sleep(RESUME_DELAY).await;
+
+            // Members who left since still have their reaction removed
+            if let Ok(mut member) = guild_id.member(cx, user.id).await {
+                if !member.roles.contains(&role_id) {
+                    info!("Assigning talk role to {}", &user.id);
+                    // The reaction is kept so the next start tries again
+                    if let Err(e) = member.add_role(cx, role_id).await {
+                        error!("Unable to assign the talk role to {}: {}", user.id, e);
+                        continue;
+                    }
+                }
+            }
+
+            if let Err(e) = channel_id
+                .delete_reaction(cx, message_id, Some(user.id), emoji.clone())
+                .await
+            {
+                error!(
+                    "Unable to remove the welcome reaction of {}: {}",
+                    user.id, e
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
 pub async fn help(args: Arc<Args>) -> Result<(), Error> {
     let help_string = format!(
         "
@@ -127,10 +339,43 @@ Post the welcome message to `channel`
 
 ```
 will post the welcome message to the `channel` specified.  
+
+Change the text of the welcome message
+```
+{set_command}
+```
+will update the posted welcome message and use the new text from now on.  
+
+Members react to the welcome message with ✅ to get the talk role, use a different emoji in this
+server with
+```
+{emoji_command}
+```
+before posting the welcome message.  The text and emoji fall back to the `welcome_text` and
+`welcome_emoji` settings, which are shared by every server.  
 ",
-        command = "?CoC {channel}"
+        command = "?CoC {channel}",
+        set_command = "?CoC set text...",
+        emoji_command = "?CoC emoji 👋"
     );
 
     api::send_reply(args.clone(), &help_string).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn welcome_settings_are_keyed_by_guild() {
+        assert_eq!(
+            guild_setting_key(WELCOME_TEXT_SETTING, Some(GuildId(1234))),
+            "welcome_text:1234"
+        );
+        assert_eq!(
+            guild_setting_key(WELCOME_EMOJI_SETTING, None),
+            "welcome_emoji"
+        );
+    }
+}